@@ -0,0 +1,45 @@
+//! With the `ffi` feature, compiles fastText's C++ sources and the C shim over them
+//! (`ffi/fasttext_shim.cc`) into a static library linked into the crate. The sources are read
+//! from `vendor/fastText`, or from the fastText checkout named by `FASTTEXT_SRC`.
+
+#[cfg(feature = "ffi")]
+extern crate cc;
+
+#[cfg(feature = "ffi")]
+fn main() {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-changed=ffi/fasttext_shim.h");
+    println!("cargo:rerun-if-changed=ffi/fasttext_shim.cc");
+    println!("cargo:rerun-if-env-changed=FASTTEXT_SRC");
+    let root = env::var_os("FASTTEXT_SRC").map_or_else(|| PathBuf::from("vendor/fastText"), PathBuf::from);
+    let src = root.join("src");
+    println!("cargo:rerun-if-changed={}", src.display());
+    let sources: Vec<PathBuf> = match fs::read_dir(&src) {
+        Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "cc") && !p.ends_with("main.cc"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    if sources.is_empty() {
+        panic!("the ffi feature needs fastText's sources in {} (see vendor/README.md) or a \
+                fastText checkout named by FASTTEXT_SRC", root.display());
+    }
+
+    cc::Build::new()
+        .cpp(true)
+        .flag_if_supported("-std=c++11")
+        .flag_if_supported("-pthread")
+        .warnings(false)
+        .include(&src)
+        .include("ffi")
+        .files(sources)
+        .file("ffi/fasttext_shim.cc")
+        .compile("fasttext_shim");
+    println!("cargo:rustc-link-lib=pthread");
+}
+
+#[cfg(not(feature = "ffi"))]
+fn main() {}
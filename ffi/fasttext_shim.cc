@@ -0,0 +1,172 @@
+// Implementation of fasttext_shim.h over fastText's C++ API (release 0.9.2, vendored in
+// vendor/fastText). Exceptions never cross into Rust: they are turned into error strings.
+
+#include "fasttext_shim.h"
+
+#include <cstdlib>
+#include <cstring>
+#include <exception>
+#include <sstream>
+#include <string>
+#include <utility>
+#include <vector>
+
+#include "fasttext.h"
+
+struct ft_model {
+    fasttext::FastText ft;
+};
+
+namespace {
+
+void set_error(char** err, const char* msg) {
+    if (err == nullptr) {
+        return;
+    }
+    *err = static_cast<char*>(std::malloc(std::strlen(msg) + 1));
+    if (*err != nullptr) {
+        std::strcpy(*err, msg);
+    }
+}
+
+// runs f, returning 0, or -1 with *err set if it throws.
+template <typename F>
+int guard(char** err, F f) {
+    try {
+        f();
+        return 0;
+    } catch (const std::exception& e) {
+        set_error(err, e.what());
+    } catch (...) {
+        set_error(err, "unknown fastText error");
+    }
+    return -1;
+}
+
+void send_pairs(const std::vector<std::pair<fasttext::real, std::string>>& pairs, ft_pair_cb cb, void* ctx) {
+    for (const auto& p : pairs) {
+        cb(ctx, p.second.c_str(), p.first);
+    }
+}
+
+void copy_vector(const fasttext::Vector& v, float* out) {
+    for (int64_t i = 0; i < v.size(); i++) {
+        out[i] = v[i];
+    }
+}
+
+// the model if building it succeeded, otherwise null.
+ft_model* model_or_null(ft_model* m, int status) {
+    if (status != 0) {
+        delete m;
+        return nullptr;
+    }
+    return m;
+}
+
+}  // namespace
+
+extern "C" {
+
+void ft_string_free(char* s) {
+    std::free(s);
+}
+
+ft_model* ft_load(const char* path, char** err) {
+    ft_model* m = new ft_model();
+    return model_or_null(m, guard(err, [&] { m->ft.loadModel(path); }));
+}
+
+void ft_free(ft_model* model) {
+    delete model;
+}
+
+int ft_dim(const ft_model* model) {
+    return model->ft.getDimension();
+}
+
+int ft_predict(ft_model* model, const char* text, int k, ft_pair_cb cb, void* ctx, char** err) {
+    return guard(err, [&] {
+        std::istringstream in(std::string(text) + "\n");
+        std::vector<std::pair<fasttext::real, std::string>> predictions;
+        model->ft.predictLine(in, predictions, k, 0.0);
+        send_pairs(predictions, cb, ctx);
+    });
+}
+
+int ft_word_vector(ft_model* model, const char* word, float* out, char** err) {
+    return guard(err, [&] {
+        fasttext::Vector v(model->ft.getDimension());
+        model->ft.getWordVector(v, word);
+        copy_vector(v, out);
+    });
+}
+
+int ft_sentence_vector(ft_model* model, const char* text, float* out, char** err) {
+    return guard(err, [&] {
+        std::istringstream in(std::string(text) + "\n");
+        fasttext::Vector v(model->ft.getDimension());
+        model->ft.getSentenceVector(in, v);
+        copy_vector(v, out);
+    });
+}
+
+int ft_nearest_neighbors(ft_model* model, const char* word, int k, ft_pair_cb cb, void* ctx, char** err) {
+    return guard(err, [&] { send_pairs(model->ft.getNN(word, k), cb, ctx); });
+}
+
+ft_model* ft_train(const ft_train_args* args, char** err) {
+    ft_model* m = new ft_model();
+    return model_or_null(m, guard(err, [&] {
+        fasttext::Args a;
+        a.input = args->input;
+        a.output = args->output;
+        a.lr = args->lr;
+        a.lrUpdateRate = args->lr_update_rate;
+        a.dim = args->dim;
+        a.ws = args->ws;
+        a.epoch = args->epoch;
+        a.minCount = args->min_count;
+        a.minCountLabel = args->min_count_label;
+        a.neg = args->neg;
+        a.wordNgrams = args->word_ngrams;
+        a.loss = static_cast<fasttext::loss_name>(args->loss);
+        a.model = static_cast<fasttext::model_name>(args->model);
+        a.bucket = args->bucket;
+        a.minn = args->minn;
+        a.maxn = args->maxn;
+        a.thread = args->thread;
+        a.t = args->t;
+        a.label = args->label;
+        a.verbose = 0;
+        m->ft.train(a);
+        m->ft.saveModel(a.output + ".bin");
+    }));
+}
+
+ft_model* ft_quantize(const ft_quantize_args* args, char** err) {
+    if (args->retrain != 0 && args->input == nullptr) {
+        // fastText would wait for the training data on standard input
+        set_error(err, "retraining a quantized model needs the training data");
+        return nullptr;
+    }
+    ft_model* m = new ft_model();
+    return model_or_null(m, guard(err, [&] {
+        fasttext::Args a;
+        if (args->input != nullptr) {
+            a.input = args->input;
+        }
+        a.output = args->output;
+        a.cutoff = args->cutoff;
+        a.retrain = args->retrain != 0;
+        a.qnorm = args->qnorm != 0;
+        a.qout = args->qout != 0;
+        a.dsub = args->dsub;
+        a.verbose = 0;
+        m->ft.loadModel(a.output + ".bin");
+        m->ft.quantize(a);
+        m->ft.saveModel(a.output + ".ftz");
+    }));
+}
+
+}  // extern "C"
@@ -0,0 +1,76 @@
+/* C interface over fastText's FastText class, for the `ffi` feature of the fast_text crate.
+ *
+ * Functions that can fail return 0 on success and -1 on failure, in which case *err points to
+ * a message to be released with ft_string_free. Results of variable size are passed to
+ * callbacks, so no memory allocated here outlives a call. */
+
+#ifndef FAST_TEXT_SHIM_H
+#define FAST_TEXT_SHIM_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ft_model ft_model;
+
+/* training arguments; loss and model use fastText's numbering (loss: 1 hs, 2 ns, 3 softmax,
+ * 4 ova; model: 1 cbow, 2 skipgram, 3 supervised) */
+typedef struct ft_train_args {
+    const char* input;
+    const char* output;
+    double lr;
+    int lr_update_rate;
+    int dim;
+    int ws;
+    int epoch;
+    int min_count;
+    int min_count_label;
+    int neg;
+    int word_ngrams;
+    int loss;
+    int model;
+    int bucket;
+    int minn;
+    int maxn;
+    int thread;
+    double t;
+    const char* label;
+} ft_train_args;
+
+typedef struct ft_quantize_args {
+    const char* input;
+    const char* output;
+    size_t cutoff;
+    int retrain;
+    int qnorm;
+    int qout;
+    size_t dsub;
+} ft_quantize_args;
+
+/* receives one (label or word, score) pair of a result */
+typedef void (*ft_pair_cb)(void* ctx, const char* name, float score);
+
+void ft_string_free(char* s);
+
+ft_model* ft_load(const char* path, char** err);
+void ft_free(ft_model* model);
+int ft_dim(const ft_model* model);
+
+int ft_predict(ft_model* model, const char* text, int k, ft_pair_cb cb, void* ctx, char** err);
+/* vectors are written to out, which has room for ft_dim floats */
+int ft_word_vector(ft_model* model, const char* word, float* out, char** err);
+int ft_sentence_vector(ft_model* model, const char* text, float* out, char** err);
+int ft_nearest_neighbors(ft_model* model, const char* word, int k, ft_pair_cb cb, void* ctx, char** err);
+
+/* trains a model and saves it to <output>.bin */
+ft_model* ft_train(const ft_train_args* args, char** err);
+/* quantizes <output>.bin and saves the result to <output>.ftz; retrain needs input */
+ft_model* ft_quantize(const ft_quantize_args* args, char** err);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,249 @@
+//! Hyperparameters stored in the header of fastText model files.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use bin_io::*;
+
+/// The kind of model (fastText's `model_name`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelName {
+    Cbow,
+    Skipgram,
+    Supervised,
+}
+
+impl ModelName {
+    fn from_i32(v: i32) -> io::Result<ModelName> {
+        match v {
+            1 => Ok(ModelName::Cbow),
+            2 => Ok(ModelName::Skipgram),
+            3 => Ok(ModelName::Supervised),
+            _ => Err(invalid("unknown model type in model file")),
+        }
+    }
+
+    pub(crate) fn to_i32(self) -> i32 {
+        match self {
+            ModelName::Cbow => 1,
+            ModelName::Skipgram => 2,
+            ModelName::Supervised => 3,
+        }
+    }
+
+    /// the fastText command that trains this kind of model.
+    pub fn command(self) -> &'static str {
+        match self {
+            ModelName::Cbow => "cbow",
+            ModelName::Skipgram => "skipgram",
+            ModelName::Supervised => "supervised",
+        }
+    }
+}
+
+/// The loss function (fastText's `loss_name`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LossName {
+    HierarchicalSoftmax,
+    NegativeSampling,
+    Softmax,
+    OneVsAll,
+}
+
+impl LossName {
+    fn from_i32(v: i32) -> io::Result<LossName> {
+        match v {
+            1 => Ok(LossName::HierarchicalSoftmax),
+            2 => Ok(LossName::NegativeSampling),
+            3 => Ok(LossName::Softmax),
+            4 => Ok(LossName::OneVsAll),
+            _ => Err(invalid("unknown loss in model file")),
+        }
+    }
+
+    pub(crate) fn to_i32(self) -> i32 {
+        match self {
+            LossName::HierarchicalSoftmax => 1,
+            LossName::NegativeSampling => 2,
+            LossName::Softmax => 3,
+            LossName::OneVsAll => 4,
+        }
+    }
+
+    /// the value of fastText's `-loss` argument.
+    pub fn name(self) -> &'static str {
+        match self {
+            LossName::HierarchicalSoftmax => "hs",
+            LossName::NegativeSampling => "ns",
+            LossName::Softmax => "softmax",
+            LossName::OneVsAll => "ova",
+        }
+    }
+}
+
+/// Training arguments. Only the dictionary and training settings are saved in model files;
+/// the rest keep fastText's defaults when a model is loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainArgs {
+    pub lr: f64,
+    pub lr_update_rate: i32,
+    pub dim: i32,
+    pub ws: i32,
+    pub epoch: i32,
+    pub min_count: i32,
+    pub min_count_label: i32,
+    pub neg: i32,
+    pub word_ngrams: i32,
+    pub loss: LossName,
+    pub model: ModelName,
+    pub bucket: i32,
+    pub minn: i32,
+    pub maxn: i32,
+    pub thread: i32,
+    pub t: f64,
+    pub label: String,
+    pub qout: bool,
+}
+
+impl Default for TrainArgs {
+    /// fastText's defaults for unsupervised (skipgram) training.
+    fn default() -> TrainArgs {
+        TrainArgs {
+            lr: 0.05,
+            lr_update_rate: 100,
+            dim: 100,
+            ws: 5,
+            epoch: 5,
+            min_count: 5,
+            min_count_label: 0,
+            neg: 5,
+            word_ngrams: 1,
+            loss: LossName::NegativeSampling,
+            model: ModelName::Skipgram,
+            bucket: 2000000,
+            minn: 3,
+            maxn: 6,
+            thread: 12,
+            t: 1e-4,
+            label: "__label__".to_string(),
+            qout: false,
+        }
+    }
+}
+
+impl TrainArgs {
+    /// fastText's defaults for supervised training.
+    pub fn supervised() -> TrainArgs {
+        TrainArgs {
+            lr: 0.1,
+            min_count: 1,
+            loss: LossName::Softmax,
+            model: ModelName::Supervised,
+            minn: 0,
+            maxn: 0,
+            ..TrainArgs::default()
+        }
+    }
+
+    /// reads the arguments in the order fastText's Args::save writes them.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<TrainArgs> {
+        // fields are evaluated in source order, which is the order they were written in.
+        Ok(TrainArgs {
+            dim: read_i32(r)?,
+            ws: read_i32(r)?,
+            epoch: read_i32(r)?,
+            min_count: read_i32(r)?,
+            neg: read_i32(r)?,
+            word_ngrams: read_i32(r)?,
+            loss: LossName::from_i32(read_i32(r)?)?,
+            model: ModelName::from_i32(read_i32(r)?)?,
+            bucket: read_i32(r)?,
+            minn: read_i32(r)?,
+            maxn: read_i32(r)?,
+            lr_update_rate: read_i32(r)?,
+            t: read_f64(r)?,
+            ..TrainArgs::default()
+        })
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for &v in &[self.dim, self.ws, self.epoch, self.min_count, self.neg, self.word_ngrams,
+                    self.loss.to_i32(), self.model.to_i32(), self.bucket, self.minn, self.maxn,
+                    self.lr_update_rate] {
+            write_i32(w, v)?;
+        }
+        write_f64(w, self.t)
+    }
+
+    /// The settings saved in model files as arguments for the training functions (without
+    /// `input` and `output`), so a model can be retrained with them:
+    ///
+    /// ```no_run
+    /// # use fast_text::FastTextModel;
+    /// let model = FastTextModel::load("sample.bin").unwrap();
+    /// let args = model.args().cli_args();
+    /// let mut args: std::collections::HashMap<&str, &str> = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    /// args.insert("input", "sample_text.txt");
+    /// args.insert("output", "retrained");
+    /// fast_text::skipgram(&args);
+    /// ```
+    pub fn cli_args(&self) -> HashMap<&'static str, String> {
+        let mut args = HashMap::new();
+        args.insert("dim", self.dim.to_string());
+        args.insert("ws", self.ws.to_string());
+        args.insert("epoch", self.epoch.to_string());
+        args.insert("minCount", self.min_count.to_string());
+        args.insert("neg", self.neg.to_string());
+        args.insert("wordNgrams", self.word_ngrams.to_string());
+        args.insert("loss", self.loss.name().to_string());
+        args.insert("bucket", self.bucket.to_string());
+        args.insert("minn", self.minn.to_string());
+        args.insert("maxn", self.maxn.to_string());
+        args.insert("lrUpdateRate", self.lr_update_rate.to_string());
+        args.insert("t", self.t.to_string());
+        args
+    }
+}
+
+/// Settings for quantizing a supervised model (the quantization arguments of `quantize`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizeArgs {
+    /// number of words and n-grams to retain, by norm of their vectors; 0 keeps all of them
+    pub cutoff: usize,
+    /// finetune embeddings if a cutoff is applied
+    pub retrain: bool,
+    /// quantize the norms of the vectors separately
+    pub qnorm: bool,
+    /// quantize the classifier (output matrix) too
+    pub qout: bool,
+    /// size of each sub-vector
+    pub dsub: usize,
+}
+
+impl Default for QuantizeArgs {
+    fn default() -> QuantizeArgs {
+        QuantizeArgs {
+            cutoff: 0,
+            retrain: false,
+            qnorm: false,
+            qout: false,
+            dsub: 2,
+        }
+    }
+}
+
+impl QuantizeArgs {
+    /// The settings as arguments for the `quantize` function. fastText's boolean flags take no
+    /// value, so set ones map to an empty string and unset ones are left out.
+    pub fn cli_args(&self) -> HashMap<&'static str, String> {
+        let mut args = HashMap::new();
+        args.insert("cutoff", self.cutoff.to_string());
+        args.insert("dsub", self.dsub.to_string());
+        for &(flag, set) in &[("retrain", self.retrain), ("qnorm", self.qnorm), ("qout", self.qout)] {
+            if set {
+                args.insert(flag, String::new());
+            }
+        }
+        args
+    }
+}
@@ -0,0 +1,695 @@
+//! How the command functions of this crate (`supervised`, `predict`, `nn`, ...) run fastText.
+//!
+//! By default they run the `./fasttext` binary, installing it first if needed. A
+//! `MockBackend` replays canned output instead, so code built on those functions can be
+//! tested without the binary:
+//!
+//! ```
+//! use std::rc::Rc;
+//! use fast_text::backend::{self, MockBackend};
+//!
+//! let mock = Rc::new(MockBackend::new()
+//!     .on("predict model.bin input.txt 1", "__label__sauce\n__label__cheese\n"));
+//! backend::set_backend(mock.clone());
+//! assert_eq!(fast_text::predict("model.bin", "input.txt", 1),
+//!            vec![vec!["__label__sauce".to_string()], vec!["__label__cheese".to_string()]]);
+//! assert_eq!(mock.calls().len(), 1);
+//! ```
+
+use std::cell::RefCell;
+#[cfg(any(feature = "subprocess", feature = "native", feature = "ffi"))]
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use bin_io::invalid;
+use {install, DEBUG};
+#[cfg(feature = "subprocess")]
+use {cmd_error, gen_cmd, label_probs, parse_vec_output, resp, LossName, VERSION};
+#[cfg(feature = "native")]
+use matrix::Matrix;
+#[cfg(feature = "native")]
+use {FastTextModel, Trainer};
+#[cfg(feature = "ffi")]
+use ffi;
+use {QuantizeArgs, TrainArgs};
+
+/// What a fastText command printed, and whether it succeeded.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// the output of a successful command that printed `stdout`.
+    pub fn ok(stdout: &str) -> CommandOutput {
+        CommandOutput { success: true, stdout: stdout.to_string(), stderr: String::new() }
+    }
+}
+
+/// Runs fastText commands.
+pub trait Backend {
+    /// Runs `fasttext <cmd>` (e.g. `predict model.bin test.txt 1`), with `stdin` as its
+    /// standard input if given.
+    fn run(&self, cmd: &str, stdin: Option<&str>) -> io::Result<CommandOutput>;
+}
+
+/// Runs the `./fasttext` binary in the working directory, installing it if it is missing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Subprocess;
+
+/// held while checking for the binary, so that threads wait for a single install.
+static INSTALL: Mutex<()> = Mutex::new(());
+
+impl Backend for Subprocess {
+    fn run(&self, cmd: &str, stdin: Option<&str>) -> io::Result<CommandOutput> {
+        let installing = INSTALL.lock().unwrap_or_else(|e| e.into_inner());
+        if !Path::new("./fasttext").exists() {
+            for o in install().iter() {
+                if !o.status.success() {
+                    return Err(io::Error::other("could not install fastText"));
+                }
+            }
+        }
+        drop(installing);
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(String::from("./fasttext ") + cmd)
+            .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(input) = stdin {
+            // dropped at the end of the statement, closing stdin
+            child.stdin.take().unwrap().write_all(input.as_bytes())?;
+        }
+        let o = child.wait_with_output()?;
+        Ok(CommandOutput {
+            success: o.status.success(),
+            stdout: String::from_utf8_lossy(&o.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&o.stderr).into_owned(),
+        })
+    }
+}
+
+/// A command run by a `MockBackend`, with what it answered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub cmd: String,
+    pub stdin: Option<String>,
+    pub output: CommandOutput,
+}
+
+/// A backend for tests. It answers commands with canned outputs (given with `on`, or loaded
+/// from a file saved by a recording mock) and keeps a log of every command it was asked to
+/// run. Commands without a canned output fail.
+pub struct MockBackend {
+    /// canned outputs by command, and stdin if it has to match too
+    responses: Vec<(String, Option<String>, CommandOutput)>,
+    /// backend whose answers are recorded, if recording
+    inner: Option<Box<dyn Backend>>,
+    calls: Mutex<Vec<Call>>,
+}
+
+impl MockBackend {
+    pub fn new() -> MockBackend {
+        MockBackend { responses: Vec::new(), inner: None, calls: Mutex::new(Vec::new()) }
+    }
+
+    /// A mock that runs commands with `inner` and records what they print, to be saved with
+    /// `save` and replayed with `load`.
+    pub fn record(inner: Box<dyn Backend>) -> MockBackend {
+        MockBackend { inner: Some(inner), ..MockBackend::new() }
+    }
+
+    /// Answers `cmd`, whatever its standard input, with a successful run printing `stdout`.
+    pub fn on(self, cmd: &str, stdout: &str) -> MockBackend {
+        self.on_output(cmd, None, CommandOutput::ok(stdout))
+    }
+
+    /// Answers `cmd` with `output`, only when its standard input is `stdin` if given. Used
+    /// for commands reading queries from standard input, like `nn` and `print-word-vectors`.
+    pub fn on_output(mut self, cmd: &str, stdin: Option<&str>, output: CommandOutput) -> MockBackend {
+        self.responses.push((cmd.to_string(), stdin.map(str::to_string), output));
+        self
+    }
+
+    /// The commands run so far, oldest first.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Writes the commands run so far with their outputs to `path`, for `load`. The file is
+    /// text, one `<field> <value>` line per field, where the value of a string field is its
+    /// length in bytes and the string follows on its own line:
+    ///
+    /// ```text
+    /// cmd 17
+    /// predict m.bin - 1
+    /// stdin 6
+    /// pizza
+    ///
+    /// success true
+    /// stdout 16
+    /// __label__cheese
+    ///
+    /// stderr 0
+    ///
+    /// ```
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        for c in &self.calls() {
+            write_field(&mut w, "cmd", &c.cmd)?;
+            if let Some(ref stdin) = c.stdin {
+                write_field(&mut w, "stdin", stdin)?;
+            }
+            writeln!(w, "success {}", c.output.success)?;
+            write_field(&mut w, "stdout", &c.output.stdout)?;
+            write_field(&mut w, "stderr", &c.output.stderr)?;
+        }
+        w.flush()
+    }
+
+    /// A mock replaying the commands saved by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<MockBackend> {
+        let mut r = BufReader::new(File::open(path)?);
+        let mut mock = MockBackend::new();
+        while let Some(header) = read_header(&mut r)? {
+            let cmd = read_field(&mut r, header, "cmd")?;
+            let mut header = expect_header(&mut r)?;
+            let stdin = if header.0 == "stdin" {
+                let stdin = read_field(&mut r, header, "stdin")?;
+                header = expect_header(&mut r)?;
+                Some(stdin)
+            } else {
+                None
+            };
+            let success = match (header.0.as_str(), header.1.as_str()) {
+                ("success", "true") => true,
+                ("success", "false") => false,
+                _ => return Err(invalid("expected success true or false in recorded commands")),
+            };
+            let stdout = expect_header(&mut r).and_then(|h| read_field(&mut r, h, "stdout"))?;
+            let stderr = expect_header(&mut r).and_then(|h| read_field(&mut r, h, "stderr"))?;
+            mock = mock.on_output(&cmd, stdin.as_deref(), CommandOutput { success, stdout, stderr });
+        }
+        Ok(mock)
+    }
+}
+
+fn write_field<W: Write>(w: &mut W, name: &str, value: &str) -> io::Result<()> {
+    writeln!(w, "{} {}", name, value.len())?;
+    w.write_all(value.as_bytes())?;
+    writeln!(w)
+}
+
+/// the name and value of a `<field> <value>` line, None at the end of the file.
+fn read_header<R: BufRead>(r: &mut R) -> io::Result<Option<(String, String)>> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let line = line.trim_end_matches('\n');
+    match line.find(' ') {
+        Some(i) => Ok(Some((line[..i].to_string(), line[i + 1..].to_string()))),
+        None => Err(invalid("malformed line in recorded commands")),
+    }
+}
+
+fn expect_header<R: BufRead>(r: &mut R) -> io::Result<(String, String)> {
+    read_header(r)?.ok_or_else(|| invalid("truncated recorded commands"))
+}
+
+/// the string of a field, whose length is the header's value.
+fn read_field<R: BufRead>(r: &mut R, header: (String, String), name: &str) -> io::Result<String> {
+    if header.0 != name {
+        return Err(invalid(&format!("expected {} in recorded commands but found {}", name, header.0)));
+    }
+    let len = header.1.parse::<u64>().map_err(|_| invalid("malformed length in recorded commands"))?;
+    let mut b = Vec::new();
+    r.take(len + 1).read_to_end(&mut b)?;
+    if (b.len() as u64) < len + 1 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated recorded commands"));
+    }
+    if b.pop() != Some(b'\n') {
+        return Err(invalid("missing newline after a field of recorded commands"));
+    }
+    String::from_utf8(b).map_err(|_| invalid("recorded commands are not valid UTF-8"))
+}
+
+impl Default for MockBackend {
+    fn default() -> MockBackend {
+        MockBackend::new()
+    }
+}
+
+impl Backend for MockBackend {
+    fn run(&self, cmd: &str, stdin: Option<&str>) -> io::Result<CommandOutput> {
+        let output = match self.inner {
+            Some(ref inner) => inner.run(cmd, stdin)?,
+            None => self.responses.iter()
+                .find(|&(c, s, _)| c == cmd && s.as_ref().is_none_or(|s| Some(s.as_str()) == stdin))
+                .map(|r| r.2.clone())
+                .unwrap_or_else(|| CommandOutput {
+                    success: false,
+                    stdout: String::new(),
+                    stderr: format!("no canned output for: {}", cmd),
+                }),
+        };
+        self.calls.lock().unwrap().push(Call { cmd: cmd.to_string(), stdin: stdin.map(str::to_string), output: output.clone() });
+        Ok(output)
+    }
+}
+
+thread_local! {
+    static BACKEND: RefCell<Rc<dyn Backend>> = RefCell::new(Rc::new(Subprocess));
+}
+
+/// Makes the command functions run through `backend` on the current thread, and returns the
+/// backend they used before.
+pub fn set_backend(backend: Rc<dyn Backend>) -> Rc<dyn Backend> {
+    BACKEND.with(|b| b.replace(backend))
+}
+
+/// runs a command with the current thread's backend; failures to run it at all are reported
+/// as a failed command.
+pub(crate) fn run(cmd: &str, stdin: Option<&str>) -> CommandOutput {
+    if DEBUG { println!("cmd: {}", cmd); }
+    let backend = BACKEND.with(|b| b.borrow().clone());
+    let r = backend.run(cmd, stdin).unwrap_or_else(|e| CommandOutput {
+        success: false,
+        stdout: String::new(),
+        stderr: e.to_string(),
+    });
+    if DEBUG { println!("{:?}", r); }
+    r
+}
+
+/// The main fastText operations, so code can be written once and run with whichever engine
+/// suits it: `CommandLine` (the fastText binary, feature `subprocess`), `Native` (this
+/// crate's own implementation, feature `native`) or `Ffi` (fastText compiled into the crate,
+/// feature `ffi`). The first two features are on by default. Models are referred to by path.
+///
+/// ```no_run
+/// use fast_text::backend::{self, FastTextBackend};
+///
+/// fn top_label(b: &dyn FastTextBackend, text: &str) -> std::io::Result<Option<String>> {
+///     let model = b.train(&fast_text::TrainArgs::supervised(), "sample_text.txt", "sample")?;
+///     Ok(b.predict(&model, text, 1)?.pop().map(|p| p.0))
+/// }
+///
+/// let label = top_label(&*backend::default_backend(), "cheese sauce").unwrap();
+/// ```
+pub trait FastTextBackend {
+    /// Trains a model on `input` and saves it to `<output>.bin`, returning that path.
+    fn train(&self, args: &TrainArgs, input: &str, output: &str) -> io::Result<String>;
+
+    /// Top k labels for a line of text with their probabilities, most likely first.
+    fn predict(&self, model: &str, text: &str, k: usize) -> io::Result<Vec<(String, f32)>>;
+
+    /// Vector for a word (see `print-word-vectors`).
+    fn word_vector(&self, model: &str, word: &str) -> io::Result<Vec<f32>>;
+
+    /// Vector for a line of text (see `print-sentence-vectors`).
+    fn sentence_vector(&self, model: &str, sentence: &str) -> io::Result<Vec<f32>>;
+
+    /// The k words closest to a word, most similar first (see `nn`).
+    fn nearest_neighbors(&self, model: &str, word: &str, k: usize) -> io::Result<Vec<(String, f32)>>;
+
+    /// Quantizes the supervised model `<output>.bin` into `<output>.ftz` (see `quantize`),
+    /// returning the path of the compressed model. `input` is the training data, which
+    /// `qargs.retrain` needs to fine-tune the pruned model.
+    fn quantize(&self, output: &str, qargs: &QuantizeArgs, input: Option<&str>) -> io::Result<String>;
+}
+
+/// the error of every engine for retraining without the training data.
+#[cfg(any(feature = "ffi", feature = "subprocess", feature = "native"))]
+fn check_retrain_input(qargs: &QuantizeArgs, input: Option<&str>) -> io::Result<()> {
+    if qargs.retrain && input.is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "retraining a quantized model needs the training data"));
+    }
+    Ok(())
+}
+
+/// The preferred engine among those compiled in: fastText in this process with the `ffi`
+/// feature, then the fastText binary with `subprocess` (on by default), then the native one.
+#[cfg(any(feature = "ffi", feature = "subprocess", feature = "native"))]
+pub fn default_backend() -> Box<dyn FastTextBackend> {
+    #[cfg(feature = "ffi")]
+    return Box::new(Ffi::default());
+    #[cfg(all(not(feature = "ffi"), feature = "subprocess"))]
+    return Box::new(CommandLine);
+    #[cfg(all(not(feature = "ffi"), not(feature = "subprocess")))]
+    return Box::new(Native::default());
+}
+
+/// Runs the fastText binary like the command functions of this crate, through the current
+/// thread's `Backend` (so it can be mocked too). Exactly what fastText computes, at the cost of
+/// a process per call and of needing the binary.
+#[cfg(feature = "subprocess")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandLine;
+
+/// The losses the fastText release that `install` downloads can train with.
+#[cfg(feature = "subprocess")]
+const CLI_LOSSES: &[LossName] = &[LossName::HierarchicalSoftmax, LossName::NegativeSampling, LossName::Softmax,
+                                  LossName::OneVsAll];
+
+#[cfg(feature = "subprocess")]
+impl CommandLine {
+    /// runs a command, turning a failure into an error.
+    fn run(&self, cmd: &str, stdin: Option<&str>) -> io::Result<String> {
+        let o = run(cmd, stdin);
+        if o.success { Ok(o.stdout) } else { Err(cmd_error(cmd, &o)) }
+    }
+
+    fn run_args(&self, command: &str, args: &HashMap<&'static str, String>) -> io::Result<String> {
+        let args: HashMap<&str, &str> = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        self.run(&gen_cmd(command.to_string(), &args), None)
+    }
+
+    fn vector(&self, cmd: &str, input: &str, words: bool) -> io::Result<Vec<f32>> {
+        let stdout = self.run(cmd, Some(&(input.to_string() + "\n")))?;
+        parse_vec_output(stdout, words).into_iter().next()
+            .map(|v| v.into_iter().map(|x| x as f32).collect())
+            .ok_or_else(|| invalid(&format!("no vector in the output of {}", cmd)))
+    }
+}
+
+#[cfg(feature = "subprocess")]
+impl FastTextBackend for CommandLine {
+    fn train(&self, args: &TrainArgs, input: &str, output: &str) -> io::Result<String> {
+        if !CLI_LOSSES.contains(&args.loss) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "fastText {} cannot train with the {} loss", VERSION, args.loss.name())));
+        }
+        let mut cli = args.cli_args();
+        cli.insert("lr", args.lr.to_string());
+        cli.insert("minCountLabel", args.min_count_label.to_string());
+        cli.insert("thread", args.thread.to_string());
+        cli.insert("label", args.label.clone());
+        cli.insert("input", input.to_string());
+        cli.insert("output", output.to_string());
+        self.run_args(args.model.command(), &cli)?;
+        Ok(output.to_string() + ".bin")
+    }
+
+    fn predict(&self, model: &str, text: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        let stdout = self.run(&format!("predict-prob {} - {}", model, k), Some(&(text.to_string() + "\n")))?;
+        Ok(stdout.lines().next().map_or_else(Vec::new, label_probs).into_iter()
+            .map(|(l, p)| (l, p as f32)).collect())
+    }
+
+    fn word_vector(&self, model: &str, word: &str) -> io::Result<Vec<f32>> {
+        self.vector(&format!("print-word-vectors {}", model), word, true)
+    }
+
+    fn sentence_vector(&self, model: &str, sentence: &str) -> io::Result<Vec<f32>> {
+        self.vector(&format!("print-sentence-vectors {}", model), sentence, false)
+    }
+
+    fn nearest_neighbors(&self, model: &str, word: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        let stdout = self.run(&format!("nn {} {}", model, k), Some(&(word.to_string() + "\n")))?;
+        Ok(resp("Query word? ", &stdout).into_iter().next().unwrap_or_default().into_iter()
+            .map(|(w, s)| (w, s as f32)).collect())
+    }
+
+    fn quantize(&self, output: &str, qargs: &QuantizeArgs, input: Option<&str>) -> io::Result<String> {
+        check_retrain_input(qargs, input)?;
+        let mut cli = qargs.cli_args();
+        cli.insert("output", output.to_string());
+        if let Some(input) = input {
+            cli.insert("input", input.to_string());
+        }
+        self.run_args("quantize", &cli)?;
+        Ok(output.to_string() + ".ftz")
+    }
+}
+
+/// Runs fastText's algorithms with this crate's own implementation (`FastTextModel` and
+/// `Trainer`): no binary or process needed, and loaded models are kept for later calls. The
+/// models are compatible with fastText's, but training does not give the same weights.
+#[cfg(feature = "native")]
+#[derive(Default)]
+pub struct Native {
+    models: RefCell<HashMap<String, Rc<FastTextModel>>>,
+    // the normalized word vectors of loaded models, computed by their first nearest-neighbor query
+    unit_vectors: RefCell<HashMap<String, Rc<Matrix>>>,
+}
+
+#[cfg(feature = "native")]
+impl Native {
+    /// the model at a path, loading it on first use.
+    fn model(&self, path: &str) -> io::Result<Rc<FastTextModel>> {
+        if let Some(m) = self.models.borrow().get(path) {
+            return Ok(m.clone());
+        }
+        let m = Rc::new(FastTextModel::load(path)?);
+        self.models.borrow_mut().insert(path.to_string(), m.clone());
+        Ok(m)
+    }
+
+    /// saves a model, replacing any loaded copy of the file.
+    fn save(&self, model: FastTextModel, path: String) -> io::Result<String> {
+        model.save(&path)?;
+        self.insert(path.clone(), model);
+        Ok(path)
+    }
+
+    /// keeps a model as the one at `path`, dropping what was computed for the previous one.
+    fn insert(&self, path: String, model: FastTextModel) {
+        self.unit_vectors.borrow_mut().remove(&path);
+        self.models.borrow_mut().insert(path, Rc::new(model));
+    }
+
+    /// the normalized word vectors of the model at a path, computed on first use.
+    fn unit_vectors(&self, path: &str, model: &FastTextModel) -> Rc<Matrix> {
+        if let Some(v) = self.unit_vectors.borrow().get(path) {
+            return v.clone();
+        }
+        let v = Rc::new(model.unit_word_vectors());
+        self.unit_vectors.borrow_mut().insert(path.to_string(), v.clone());
+        v
+    }
+}
+
+#[cfg(feature = "native")]
+impl FastTextBackend for Native {
+    fn train(&self, args: &TrainArgs, input: &str, output: &str) -> io::Result<String> {
+        self.save(Trainer::new(args.clone()).train(input)?, output.to_string() + ".bin")
+    }
+
+    fn predict(&self, model: &str, text: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        Ok(self.model(model)?.predict(text, k))
+    }
+
+    fn word_vector(&self, model: &str, word: &str) -> io::Result<Vec<f32>> {
+        Ok(self.model(model)?.word_vector(word))
+    }
+
+    fn sentence_vector(&self, model: &str, sentence: &str) -> io::Result<Vec<f32>> {
+        Ok(self.model(model)?.sentence_vector(sentence))
+    }
+
+    fn nearest_neighbors(&self, model: &str, word: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        let m = self.model(model)?;
+        Ok(m.nearest_neighbors_in(&self.unit_vectors(model, &m), word, k))
+    }
+
+    fn quantize(&self, output: &str, qargs: &QuantizeArgs, input: Option<&str>) -> io::Result<String> {
+        check_retrain_input(qargs, input)?;
+        let ftz = output.to_string() + ".ftz";
+        let quantized = self.model(&(output.to_string() + ".bin"))?.quantize(qargs, &ftz, input.map(Path::new))?;
+        self.insert(ftz.clone(), quantized);
+        Ok(ftz)
+    }
+}
+
+/// Runs fastText compiled into the crate (see `ffi`): exactly what the binary computes, without
+/// a process per call, at the cost of building fastText's C++ sources. Loaded models are kept
+/// for later calls.
+#[cfg(feature = "ffi")]
+#[derive(Default)]
+pub struct Ffi {
+    models: RefCell<HashMap<String, Rc<ffi::Model>>>,
+}
+
+#[cfg(feature = "ffi")]
+impl Ffi {
+    /// the model at a path, loading it on first use.
+    fn model(&self, path: &str) -> io::Result<Rc<ffi::Model>> {
+        if let Some(m) = self.models.borrow().get(path) {
+            return Ok(m.clone());
+        }
+        let m = Rc::new(ffi::Model::load(path)?);
+        self.models.borrow_mut().insert(path.to_string(), m.clone());
+        Ok(m)
+    }
+}
+
+#[cfg(feature = "ffi")]
+impl FastTextBackend for Ffi {
+    fn train(&self, args: &TrainArgs, input: &str, output: &str) -> io::Result<String> {
+        let bin = output.to_string() + ".bin";
+        let model = ffi::Model::train(args, input, output)?;
+        self.models.borrow_mut().insert(bin.clone(), Rc::new(model));
+        Ok(bin)
+    }
+
+    fn predict(&self, model: &str, text: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        self.model(model)?.predict(text, k)
+    }
+
+    fn word_vector(&self, model: &str, word: &str) -> io::Result<Vec<f32>> {
+        self.model(model)?.word_vector(word)
+    }
+
+    fn sentence_vector(&self, model: &str, sentence: &str) -> io::Result<Vec<f32>> {
+        self.model(model)?.sentence_vector(sentence)
+    }
+
+    fn nearest_neighbors(&self, model: &str, word: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        self.model(model)?.nearest_neighbors(word, k)
+    }
+
+    fn quantize(&self, output: &str, qargs: &QuantizeArgs, input: Option<&str>) -> io::Result<String> {
+        check_retrain_input(qargs, input)?;
+        let ftz = output.to_string() + ".ftz";
+        let model = ffi::Model::quantize(output, qargs, input.map(Path::new))?;
+        self.models.borrow_mut().insert(ftz.clone(), Rc::new(model));
+        Ok(ftz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn test_mock_backend() {
+        let mock = MockBackend::new()
+            .on("predict m.bin t.txt 1", "__label__a\n")
+            .on_output("nn m.bin 10", Some("cheese\n"), CommandOutput::ok("Query word? sauce 0.9\n"));
+        assert_eq!(mock.run("predict m.bin t.txt 1", None).unwrap().stdout, "__label__a\n");
+        assert!(mock.run("nn m.bin 10", Some("cheese\n")).unwrap().success);
+        assert!(!mock.run("nn m.bin 10", Some("pie\n")).unwrap().success);
+        let calls = mock.calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[1].stdin, Some("cheese\n".to_string()));
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let canned = MockBackend::new().on("test m.bin t.txt 1", "N\t2\nP@1\t0.5\nR@1\t0.5\n");
+        let recorder = MockBackend::record(Box::new(canned));
+        recorder.run("test m.bin t.txt 1", None).unwrap();
+        recorder.run("nn m.bin 10", Some("pie\n")).unwrap();
+        let path = env::temp_dir().join(format!("fast_text_{}_calls", process::id()));
+        recorder.save(&path).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().starts_with("cmd 18\ntest m.bin t.txt 1\nsuccess true\nstdout 20\n"));
+
+        let replay = MockBackend::load(&path).unwrap();
+        assert_eq!(replay.run("test m.bin t.txt 1", None).unwrap(), recorder.calls()[0].output);
+        let failed = replay.run("nn m.bin 10", Some("pie\n")).unwrap();
+        assert!(!failed.success && failed.stderr.starts_with("no canned output"));
+
+        let output = CommandOutput { success: true, stdout: "a\0b\n\ncmd 3\n".to_string(), stderr: String::new() };
+        let recorder = MockBackend::record(Box::new(MockBackend::new().on_output("print-word-vectors m.bin", None, output)));
+        recorder.run("print-word-vectors m.bin", Some("")).unwrap();
+        recorder.save(&path).unwrap();
+        let replay = MockBackend::load(&path).unwrap();
+        assert_eq!(replay.run("print-word-vectors m.bin", Some("")).unwrap(), recorder.calls()[0].output);
+        fs::write(&path, "cmd 40\ntest").unwrap();
+        assert_eq!(MockBackend::load(&path).err().unwrap().kind(), io::ErrorKind::UnexpectedEof);
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// succeeds at every command without printing anything.
+    #[cfg(feature = "subprocess")]
+    struct Silent;
+
+    #[cfg(feature = "subprocess")]
+    impl Backend for Silent {
+        fn run(&self, _: &str, _: Option<&str>) -> io::Result<CommandOutput> {
+            Ok(CommandOutput::ok(""))
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "subprocess")]
+    fn test_command_line_backend() {
+        let mock = Rc::new(MockBackend::new()
+            .on_output("predict-prob m.bin - 2", Some("cheese sauce\n"),
+                       CommandOutput::ok("__label__sauce 0.75 __label__cheese 0.25\n"))
+            .on_output("print-word-vectors m.bin", Some("sauce\n"), CommandOutput::ok("sauce 0.5 -1 \n"))
+            .on_output("print-sentence-vectors m.bin", Some("cheese sauce\n"), CommandOutput::ok("0.25 2 \n"))
+            .on_output("nn m.bin 1", Some("sauce\n"), CommandOutput::ok("Query word? cheese 0.5\nQuery word? ")));
+        let previous = set_backend(mock);
+        let b = CommandLine;
+        assert_eq!(b.predict("m.bin", "cheese sauce", 2).unwrap(),
+                   vec![("__label__sauce".to_string(), 0.75), ("__label__cheese".to_string(), 0.25)]);
+        assert_eq!(b.word_vector("m.bin", "sauce").unwrap(), vec![0.5, -1.0]);
+        assert_eq!(b.sentence_vector("m.bin", "cheese sauce").unwrap(), vec![0.25, 2.0]);
+        assert_eq!(b.nearest_neighbors("m.bin", "sauce", 1).unwrap(), vec![("cheese".to_string(), 0.5)]);
+        assert!(b.word_vector("other.bin", "sauce").is_err());
+
+        let recorder = Rc::new(MockBackend::record(Box::new(Silent)));
+        set_backend(recorder.clone());
+        assert_eq!(b.train(&TrainArgs::supervised(), "data.txt", "m").unwrap(), "m.bin");
+        let ova = TrainArgs { loss: LossName::OneVsAll, ..TrainArgs::supervised() };
+        assert_eq!(b.train(&ova, "data.txt", "m").unwrap(), "m.bin");
+        let qargs = QuantizeArgs { qnorm: true, ..QuantizeArgs::default() };
+        assert_eq!(b.quantize("m", &qargs, None).unwrap(), "m.ftz");
+        let retrain = QuantizeArgs { cutoff: 100, retrain: true, ..QuantizeArgs::default() };
+        assert_eq!(b.quantize("m", &retrain, None).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(b.quantize("m", &retrain, Some("data.txt")).unwrap(), "m.ftz");
+        let calls = recorder.calls();
+        assert!(calls[0].cmd.starts_with("supervised -bucket 2000000 -dim 100 "));
+        assert!(calls[0].cmd.contains(" -input data.txt ") && calls[0].cmd.contains(" -lr 0.1 "));
+        assert!(calls[1].cmd.contains(" -loss ova "));
+        assert_eq!(calls[2].cmd, "quantize -cutoff 0 -dsub 2 -output m -qnorm ");
+        assert_eq!(calls.len(), 4);
+        assert_eq!(calls[3].cmd, "quantize -cutoff 100 -dsub 2 -input data.txt -output m -retrain ");
+        set_backend(previous);
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_native_backend() {
+        let output = env::temp_dir().join(format!("fast_text_{}_native_backend", process::id()));
+        let output = output.to_str().unwrap();
+        let b = Native::default();
+        let args = TrainArgs { dim: 10, epoch: 5, thread: 1, bucket: 1000, ..TrainArgs::supervised() };
+        let bin = b.train(&args, "sample_text.txt", output).unwrap();
+        assert_eq!(b.predict(&bin, "our military athletes", 2).unwrap().len(), 2);
+        assert_eq!(b.word_vector(&bin, "military").unwrap().len(), 10);
+        assert_eq!(b.sentence_vector(&bin, "our military athletes").unwrap().len(), 10);
+        let nn = b.nearest_neighbors(&bin, "military", 3).unwrap();
+        assert_eq!(nn.len(), 3);
+        assert!(nn.iter().all(|n| n.0 != "military"));
+        assert_eq!(nn, FastTextModel::load(&bin).unwrap().nearest_neighbors("military", 3));
+        assert!(b.unit_vectors.borrow().contains_key(&bin));
+        assert_eq!(b.nearest_neighbors(&bin, "athletes", 2).unwrap().len(), 2);
+        b.train(&args, "sample_text.txt", output).unwrap();
+        assert!(b.unit_vectors.borrow().is_empty());
+
+        let retrain = QuantizeArgs { cutoff: 100, retrain: true, ..QuantizeArgs::default() };
+        assert_eq!(b.quantize(output, &retrain, None).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let retrain = QuantizeArgs { cutoff: 500, ..retrain };
+        let ftz = b.quantize(output, &retrain, Some("sample_text.txt")).unwrap();
+        assert!(FastTextModel::load(&ftz).unwrap().dictionary().is_pruned());
+        let ftz = b.quantize(output, &QuantizeArgs::default(), None).unwrap();
+        assert!(FastTextModel::load(&ftz).unwrap().is_quantized());
+        assert_eq!(b.predict(&ftz, "our military athletes", 1).unwrap().len(), 1);
+        fs::remove_file(&bin).unwrap();
+        fs::remove_file(&ftz).unwrap();
+    }
+}
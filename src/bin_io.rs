@@ -0,0 +1,112 @@
+//! Little-endian primitives for reading and writing fastText's binary model files.
+
+use std::io::{self, Read, Write};
+
+pub fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut b = [0; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+pub fn read_bool<R: Read>(r: &mut R) -> io::Result<bool> {
+    Ok(read_u8(r)? != 0)
+}
+
+pub fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(i32::from_le_bytes(b))
+}
+
+pub fn read_i64<R: Read>(r: &mut R) -> io::Result<i64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(i64::from_le_bytes(b))
+}
+
+pub fn read_f64<R: Read>(r: &mut R) -> io::Result<f64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(f64::from_le_bytes(b))
+}
+
+/// reads n consecutive f32s.
+pub fn read_f32s<R: Read>(r: &mut R, n: usize) -> io::Result<Vec<f32>> {
+    let len = n.checked_mul(4).ok_or_else(|| invalid("too many values"))?;
+    let b = read_bytes(r, len)?;
+    Ok(b.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+}
+
+/// reads n bytes. The buffer grows with what is actually read, so a size taken from a
+/// corrupt header fails at the end of the input instead of allocating it up front.
+pub fn read_bytes<R: Read>(r: &mut R, n: usize) -> io::Result<Vec<u8>> {
+    let mut b = Vec::new();
+    r.by_ref().take(n as u64).read_to_end(&mut b)?;
+    if b.len() < n {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input ends too early"));
+    }
+    Ok(b)
+}
+
+/// reads a NUL-terminated string.
+pub fn read_cstring<R: Read>(r: &mut R) -> io::Result<String> {
+    let mut b = Vec::new();
+    loop {
+        match read_u8(r)? {
+            0 => break,
+            c => b.push(c),
+        }
+    }
+    String::from_utf8(b).map_err(|e| invalid(&e.to_string()))
+}
+
+/// reads a non-negative size stored as a signed integer.
+pub fn read_size<R: Read>(r: &mut R) -> io::Result<usize> {
+    let v = read_i64(r)?;
+    if v < 0 {
+        return Err(invalid("negative size in model file"));
+    }
+    Ok(v as usize)
+}
+
+pub fn write_u8<W: Write>(w: &mut W, v: u8) -> io::Result<()> {
+    w.write_all(&[v])
+}
+
+pub fn write_bool<W: Write>(w: &mut W, v: bool) -> io::Result<()> {
+    write_u8(w, v as u8)
+}
+
+pub fn write_i32<W: Write>(w: &mut W, v: i32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub fn write_i64<W: Write>(w: &mut W, v: i64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub fn write_f64<W: Write>(w: &mut W, v: f64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub fn write_f32s<W: Write>(w: &mut W, vs: &[f32]) -> io::Result<()> {
+    let mut b = Vec::with_capacity(vs.len().min(1 << 16) * 4);
+    for chunk in vs.chunks(1 << 16) {
+        b.clear();
+        for v in chunk {
+            b.extend_from_slice(&v.to_le_bytes());
+        }
+        w.write_all(&b)?;
+    }
+    Ok(())
+}
+
+/// writes a string followed by NUL.
+pub fn write_cstring<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    w.write_all(s.as_bytes())?;
+    write_u8(w, 0)
+}
+
+pub fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,289 @@
+//! Conversions between word embeddings and the formats other tools expect: word2vec's binary
+//! format, GloVe's headerless text format, and a NumPy `.npy` matrix with a vocabulary file.
+//!
+//! Entries are `(word, vector)` pairs, as read by `vec_file::read_vec` or returned by
+//! `FastTextModel::word_vectors`. To turn third-party vectors into `-pretrainedVectors`:
+//!
+//! ```no_run
+//! use fast_text::{convert, vec_file};
+//!
+//! let entries = convert::read_glove("glove.6B.100d.txt").unwrap();
+//! vec_file::write_vec("glove.vec", &entries, 5).unwrap();
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use bin_io::{invalid, read_bytes, read_f32s, read_u8, write_f32s};
+use vec_file::format_g;
+
+/// dimension shared by all entries.
+fn dim(entries: &[(String, Vec<f32>)]) -> io::Result<usize> {
+    let dim = entries.first().map_or(0, |e| e.1.len());
+    if entries.iter().any(|e| e.1.len() != dim) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "vectors have different dimensions"));
+    }
+    Ok(dim)
+}
+
+/// Writes entries in word2vec's binary format: a `<words> <dim>` header line, then each word
+/// followed by a space, its vector as little-endian f32s and a newline.
+pub fn write_word2vec<P: AsRef<Path>>(path: P, entries: &[(String, Vec<f32>)]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "{} {}", entries.len(), dim(entries)?)?;
+    for (word, v) in entries {
+        write!(w, "{} ", word)?;
+        write_f32s(&mut w, v)?;
+        writeln!(w)?;
+    }
+    w.flush()
+}
+
+/// Reads word2vec's binary format. The newline after each vector is optional.
+pub fn read_word2vec<P: AsRef<Path>>(path: P) -> io::Result<Vec<(String, Vec<f32>)>> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut header = String::new();
+    r.read_line(&mut header)?;
+    let fields: Vec<usize> = header.split_whitespace().filter_map(|f| f.parse().ok()).collect();
+    if fields.len() != 2 {
+        return Err(invalid("word2vec header should be the number of words and the dimension"));
+    }
+    let (n, dim) = (fields[0], fields[1]);
+    // the header is not trusted with the allocation: a truncated file fails when read instead
+    let mut out = Vec::with_capacity(n.min(1 << 20));
+    for _ in 0..n {
+        let mut word = Vec::new();
+        loop {
+            match read_u8(&mut r)? {
+                b' ' => break,
+                b'\n' if word.is_empty() => (),
+                c => word.push(c),
+            }
+        }
+        let word = String::from_utf8(word).map_err(|_| invalid("word2vec word is not valid UTF-8"))?;
+        out.push((word, read_f32s(&mut r, dim)?));
+    }
+    Ok(out)
+}
+
+/// Writes entries in GloVe's text format: `word v1 v2 ...` lines without a header, with the
+/// given number of significant digits.
+pub fn write_glove<P: AsRef<Path>>(path: P, entries: &[(String, Vec<f32>)], precision: usize) -> io::Result<()> {
+    dim(entries)?;
+    let mut w = BufWriter::new(File::create(path)?);
+    for (word, v) in entries {
+        write!(w, "{}", word)?;
+        for x in v {
+            write!(w, " {}", format_g(*x, precision.max(1)))?;
+        }
+        writeln!(w)?;
+    }
+    w.flush()
+}
+
+/// Reads GloVe's text format. The dimension is taken from the first line.
+pub fn read_glove<P: AsRef<Path>>(path: P) -> io::Result<Vec<(String, Vec<f32>)>> {
+    let mut out: Vec<(String, Vec<f32>)> = Vec::new();
+    for (i, l) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let l = l?;
+        let mut it = l.split_whitespace();
+        let word = match it.next() {
+            Some(w) => w.to_string(),
+            None => continue,
+        };
+        let v: Vec<f32> = it.map(|t| t.parse::<f32>()).collect::<Result<_, _>>()
+            .map_err(|_| invalid(&format!("malformed number on line {} of GloVe file", i + 1)))?;
+        if out.first().is_some_and(|e| e.1.len() != v.len()) {
+            return Err(invalid(&format!("wrong number of values on line {} of GloVe file", i + 1)));
+        }
+        out.push((word, v));
+    }
+    Ok(out)
+}
+
+/// Writes the vectors as a `<words> x <dim>` float32 matrix in NumPy's `.npy` format, and the
+/// words, one per line and in the same order, to a vocabulary file.
+pub fn write_npy<P: AsRef<Path>, Q: AsRef<Path>>(matrix: P, vocabulary: Q, entries: &[(String, Vec<f32>)]) -> io::Result<()> {
+    let dim = dim(entries)?;
+    let mut w = BufWriter::new(File::create(matrix)?);
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}", entries.len(), dim);
+    // magic (6) + version (2) + header length (2) + header must be a multiple of 64 bytes
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+    w.write_all(b"\x93NUMPY\x01\x00")?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    for (_, v) in entries {
+        write_f32s(&mut w, v)?;
+    }
+    w.flush()?;
+
+    let mut w = BufWriter::new(File::create(vocabulary)?);
+    for (word, _) in entries {
+        writeln!(w, "{}", word)?;
+    }
+    w.flush()
+}
+
+/// value of a key in the header dictionary of a `.npy` file.
+fn npy_header_value<'a>(header: &'a str, key: &str) -> io::Result<&'a str> {
+    let key = format!("'{}':", key);
+    let start = header.find(&key).ok_or_else(|| invalid("incomplete .npy header"))? + key.len();
+    let rest = header[start..].trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find(',')
+    };
+    Ok(rest[..end.ok_or_else(|| invalid("malformed .npy header"))?].trim())
+}
+
+/// Reads a two dimensional float32 or float64 `.npy` matrix and its vocabulary file (one word
+/// per line, one line per row).
+pub fn read_npy<P: AsRef<Path>, Q: AsRef<Path>>(matrix: P, vocabulary: Q) -> io::Result<Vec<(String, Vec<f32>)>> {
+    let mut r = BufReader::new(File::open(matrix)?);
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic[..6] != b"\x93NUMPY" {
+        return Err(invalid("not a .npy file"));
+    }
+    let header_len = if magic[6] == 1 {
+        let mut b = [0; 2];
+        r.read_exact(&mut b)?;
+        u16::from_le_bytes(b) as usize
+    } else {
+        let mut b = [0; 4];
+        r.read_exact(&mut b)?;
+        u32::from_le_bytes(b) as usize
+    };
+    let mut header = vec![0; header_len];
+    r.read_exact(&mut header)?;
+    let header = String::from_utf8(header).map_err(|_| invalid("malformed .npy header"))?;
+
+    let descr = npy_header_value(&header, "descr")?.trim_matches(|c| c == '\'' || c == '"');
+    let fortran = npy_header_value(&header, "fortran_order")? == "True";
+    let shape: Vec<usize> = npy_header_value(&header, "shape")?.trim_matches(|c| c == '(' || c == ')')
+        .split(',').map(|s| s.trim()).filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>()).collect::<Result<_, _>>()
+        .map_err(|_| invalid("malformed .npy shape"))?;
+    if shape.len() != 2 {
+        return Err(invalid("expected a two dimensional .npy matrix"));
+    }
+    let (m, n) = (shape[0], shape[1]);
+    let len = m.checked_mul(n).ok_or_else(|| invalid(".npy matrix too large"))?;
+    let data: Vec<f32> = match descr {
+        "<f4" => read_f32s(&mut r, len)?,
+        "<f8" => {
+            let b = read_bytes(&mut r, len.checked_mul(8).ok_or_else(|| invalid(".npy matrix too large"))?)?;
+            b.chunks_exact(8).map(|c| {
+                let mut a = [0; 8];
+                a.copy_from_slice(c);
+                f64::from_le_bytes(a) as f32
+            }).collect()
+        }
+        _ => return Err(invalid("only little-endian float32 and float64 .npy files are supported")),
+    };
+
+    let words: Vec<String> = BufReader::new(File::open(vocabulary)?).lines().collect::<io::Result<_>>()?;
+    if words.len() != m {
+        return Err(invalid("vocabulary file and .npy matrix have different numbers of rows"));
+    }
+    Ok(words.into_iter().enumerate().map(|(i, w)| {
+        let v = if fortran {
+            (0..n).map(|j| data[j * m + i]).collect()
+        } else {
+            data[i * n..(i + 1) * n].to_vec()
+        };
+        (w, v)
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("fast_text_{}_{}", process::id(), name))
+    }
+
+    fn entries() -> Vec<(String, Vec<f32>)> {
+        vec![
+            ("</s>".to_string(), vec![0.5, -1.0, 0.25]),
+            ("cheese".to_string(), vec![1e-3, 2.0, -3.5]),
+            ("sauce".to_string(), vec![0.0, 0.125, 8.0]),
+        ]
+    }
+
+    #[test]
+    fn test_word2vec() {
+        let path = temp_path("w2v.bin");
+        write_word2vec(&path, &entries()).unwrap();
+        let b = fs::read(&path).unwrap();
+        assert!(b.starts_with(b"3 3\n</s> \x00\x00\x00\x3f"));
+        assert_eq!(read_word2vec(&path).unwrap(), entries());
+        fs::write(&path, b"1000000000000 3\ncheese ").unwrap();
+        assert!(read_word2vec(&path).is_err());
+        fs::write(&path, b"1 1000000000000\ncheese ").unwrap();
+        assert!(read_word2vec(&path).is_err());
+        fs::write(&path, b"1 18446744073709551615\ncheese ").unwrap();
+        assert!(read_word2vec(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_glove() {
+        let path = temp_path("glove.txt");
+        write_glove(&path, &entries(), 5).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().starts_with("</s> 0.5 -1 0.25\ncheese 0.001 2 -3.5\n"));
+        assert_eq!(read_glove(&path).unwrap(), entries());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_npy() {
+        let (matrix, vocab) = (temp_path("vectors.npy"), temp_path("vocab.txt"));
+        write_npy(&matrix, &vocab, &entries()).unwrap();
+        let b = fs::read(&matrix).unwrap();
+        let header_len = u16::from_le_bytes([b[8], b[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(b.len(), 10 + header_len + 9 * 4);
+        assert_eq!(fs::read_to_string(&vocab).unwrap(), "</s>\ncheese\nsauce\n");
+        assert_eq!(read_npy(&matrix, &vocab).unwrap(), entries());
+        fs::remove_file(&matrix).unwrap();
+        fs::remove_file(&vocab).unwrap();
+    }
+
+    #[test]
+    fn test_npy_float64_fortran() {
+        let (matrix, vocab) = (temp_path("f8.npy"), temp_path("f8.txt"));
+        let header = "{'descr': '<f8', 'fortran_order': True, 'shape': (2, 2), }\n";
+        let mut b = b"\x93NUMPY\x01\x00".to_vec();
+        b.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        b.extend_from_slice(header.as_bytes());
+        for x in &[1.0f64, 2.0, 3.0, 4.0] {
+            b.extend_from_slice(&x.to_le_bytes());
+        }
+        fs::write(&matrix, b).unwrap();
+        fs::write(&vocab, "a\nb\n").unwrap();
+        let e = read_npy(&matrix, &vocab).unwrap();
+        assert_eq!(e, vec![("a".to_string(), vec![1.0, 3.0]), ("b".to_string(), vec![2.0, 4.0])]);
+        fs::write(&vocab, "a\n").unwrap();
+        assert!(read_npy(&matrix, &vocab).is_err());
+        // a shape far larger than the file
+        let header = "{'descr': '<f8', 'fortran_order': False, 'shape': (1000000000000, 1000000), }\n";
+        let mut b = b"\x93NUMPY\x01\x00".to_vec();
+        b.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        b.extend_from_slice(header.as_bytes());
+        b.extend_from_slice(&[0; 16]);
+        fs::write(&matrix, b).unwrap();
+        assert!(read_npy(&matrix, &vocab).is_err());
+        fs::remove_file(&matrix).unwrap();
+        fs::remove_file(&vocab).unwrap();
+    }
+}
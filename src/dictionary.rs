@@ -0,0 +1,521 @@
+//! The vocabulary of a fastText model: words, labels and their character n-gram buckets.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+use args::TrainArgs;
+use bin_io::*;
+
+/// end-of-sentence token, added for every newline.
+pub const EOS: &str = "</s>";
+/// prefix and suffix added around words before computing character n-grams.
+pub const BOW: &str = "<";
+pub const EOW: &str = ">";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    Word,
+    Label,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub word: String,
+    pub count: i64,
+    pub entry_type: EntryType,
+    /// rows of the input matrix that make up the word: its own id followed by its n-gram buckets.
+    pub subwords: Vec<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    args: TrainArgs,
+    words: Vec<Entry>,
+    word2int: HashMap<String, i32>,
+    nwords: i32,
+    nlabels: i32,
+    ntokens: i64,
+    /// -1 if the dictionary was never pruned, otherwise the number of retained n-gram buckets.
+    pruneidx_size: i64,
+    pruneidx: HashMap<i32, i32>,
+}
+
+/// fastText's 32 bit FNV-1a hash. Bytes are sign-extended first, as they are by the C++ code
+/// on platforms where char is signed.
+pub fn hash(s: &str) -> u32 {
+    let mut h: u32 = 2166136261;
+    for &b in s.as_bytes() {
+        h ^= b as i8 as u32;
+        h = h.wrapping_mul(16777619);
+    }
+    h
+}
+
+/// every token of a corpus with its count, in order of first occurrence, and the number of
+/// tokens.
+pub fn count_entries<R: BufRead>(args: &TrainArgs, mut r: R) -> io::Result<(Vec<Entry>, i64)> {
+    let mut words: Vec<Entry> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut ntokens = 0i64;
+    let mut line = Vec::new();
+    while r.read_until(b'\n', &mut line)? > 0 {
+        for token in tokenize(&String::from_utf8_lossy(&line)) {
+            ntokens += 1;
+            match index.get(token) {
+                Some(&i) => words[i].count += 1,
+                None => {
+                    index.insert(token.to_string(), words.len());
+                    let entry_type = if token.starts_with(&args.label) { EntryType::Label } else { EntryType::Word };
+                    words.push(Entry { word: token.to_string(), count: 1, entry_type, subwords: Vec::new() });
+                }
+            }
+        }
+        line.clear();
+    }
+    Ok((words, ntokens))
+}
+
+/// whether an entry is frequent enough for minCount or minCountLabel.
+fn keep(args: &TrainArgs, e: &Entry) -> bool {
+    match e.entry_type {
+        EntryType::Word => e.count >= args.min_count as i64,
+        EntryType::Label => e.count >= args.min_count_label as i64,
+    }
+}
+
+/// splits a line into tokens the way fastText's readWord does, ending with EOS.
+pub fn tokenize(line: &str) -> Vec<&str> {
+    let mut out: Vec<&str> = line.split(|c| " \n\r\t\x0b\x0c\0".contains(c))
+        .filter(|t| !t.is_empty())
+        .collect();
+    out.push(EOS);
+    out
+}
+
+impl Dictionary {
+    pub fn read<R: Read>(r: &mut R, args: &TrainArgs) -> io::Result<Dictionary> {
+        let size = read_i32(r)?;
+        let nwords = read_i32(r)?;
+        let nlabels = read_i32(r)?;
+        let ntokens = read_i64(r)?;
+        let pruneidx_size = read_i64(r)?;
+        if size < 0 || nwords < 0 || nlabels < 0 || nwords + nlabels != size {
+            return Err(invalid("inconsistent dictionary sizes in model file"));
+        }
+        let mut words = Vec::with_capacity(size as usize);
+        for _ in 0..size {
+            let word = read_cstring(r)?;
+            let count = read_i64(r)?;
+            let entry_type = match read_u8(r)? {
+                0 => EntryType::Word,
+                1 => EntryType::Label,
+                _ => return Err(invalid("unknown entry type in model file")),
+            };
+            words.push(Entry { word, count, entry_type, subwords: Vec::new() });
+        }
+        if words.iter().take(nwords as usize).any(|e| e.entry_type != EntryType::Word) {
+            return Err(invalid("labels before words in model file"));
+        }
+        let mut pruneidx = HashMap::new();
+        for _ in 0..pruneidx_size.max(0) {
+            let first = read_i32(r)?;
+            let second = read_i32(r)?;
+            pruneidx.insert(first, second);
+        }
+        let mut d = Dictionary::new(args, words, ntokens);
+        d.pruneidx_size = pruneidx_size;
+        d.pruneidx = pruneidx;
+        d.init_ngrams();
+        Ok(d)
+    }
+
+    /// Dictionary over the given entries, which must list words before labels.
+    pub fn new(args: &TrainArgs, words: Vec<Entry>, ntokens: i64) -> Dictionary {
+        let nwords = words.iter().filter(|e| e.entry_type == EntryType::Word).count() as i32;
+        let mut d = Dictionary {
+            args: args.clone(),
+            word2int: HashMap::new(),
+            nwords,
+            nlabels: words.len() as i32 - nwords,
+            words,
+            ntokens,
+            pruneidx_size: -1,
+            pruneidx: HashMap::new(),
+        };
+        d.init_word2int();
+        d.init_ngrams();
+        d
+    }
+
+    /// Builds the dictionary of a training corpus the way fastText's readFromFile does: every
+    /// token is counted (with EOS for each line), words rarer than minCount and labels rarer
+    /// than minCountLabel are dropped, and words are sorted before labels by decreasing count.
+    /// Ties keep the order of first occurrence.
+    pub fn build<R: BufRead>(args: &TrainArgs, r: R) -> io::Result<Dictionary> {
+        let (mut words, ntokens) = count_entries(args, r)?;
+        words.retain(|e| keep(args, e));
+        words.sort_by_key(|e| (e.entry_type == EntryType::Label, -e.count));
+        if words.iter().all(|e| e.entry_type == EntryType::Label) {
+            return Err(invalid("empty vocabulary, try a smaller minCount"));
+        }
+        Ok(Dictionary::new(args, words, ntokens))
+    }
+
+    /// This dictionary with the words and labels of `entries` it does not have yet (and that
+    /// are frequent enough) added after its own words and labels, so existing ids only shift
+    /// for labels, by the number of new words. Counts of known entries are left unchanged.
+    pub fn extend(&self, entries: Vec<Entry>, ntokens: i64) -> Dictionary {
+        let nwords = self.nwords as usize;
+        let mut new: Vec<Entry> = entries.into_iter()
+            .filter(|e| self.get_id(&e.word).is_none() && keep(&self.args, e))
+            .collect();
+        new.sort_by_key(|e| (e.entry_type == EntryType::Label, -e.count));
+        let split = new.iter().position(|e| e.entry_type == EntryType::Label).unwrap_or(new.len());
+        let mut words = self.words[..nwords].to_vec();
+        words.extend_from_slice(&new[..split]);
+        words.extend_from_slice(&self.words[nwords..]);
+        words.extend_from_slice(&new[split..]);
+        Dictionary::new(&self.args, words, self.ntokens + ntokens)
+    }
+
+    fn init_word2int(&mut self) {
+        self.word2int = self.words.iter().enumerate().map(|(i, e)| (e.word.clone(), i as i32)).collect();
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_i32(w, self.words.len() as i32)?;
+        write_i32(w, self.nwords)?;
+        write_i32(w, self.nlabels)?;
+        write_i64(w, self.ntokens)?;
+        write_i64(w, self.pruneidx_size)?;
+        for e in self.words.iter() {
+            write_cstring(w, &e.word)?;
+            write_i64(w, e.count)?;
+            write_u8(w, match e.entry_type {
+                EntryType::Word => 0,
+                EntryType::Label => 1,
+            })?;
+        }
+        // sorted so the same dictionary is always written the same way
+        let mut pruneidx: Vec<_> = self.pruneidx.iter().collect();
+        pruneidx.sort();
+        for (&first, &second) in pruneidx {
+            write_i32(w, first)?;
+            write_i32(w, second)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps only the given input rows (word ids and n-gram rows), as fastText's
+    /// Dictionary::prune does before quantization. Labels are always kept. On return, idx
+    /// holds the retained rows in their new order: kept words by id, then kept n-grams.
+    pub fn prune(&mut self, idx: &mut Vec<i32>) {
+        let (mut words, ngrams): (Vec<i32>, Vec<i32>) = idx.iter().partition(|&&i| i < self.nwords);
+        words.sort();
+        for (j, &ngram) in ngrams.iter().enumerate() {
+            self.pruneidx.insert(ngram - self.nwords, j as i32);
+        }
+        self.pruneidx_size = self.pruneidx.len() as i64;
+
+        let keep: Vec<bool> = (0..self.words.len() as i32)
+            .map(|i| i >= self.nwords || words.binary_search(&i).is_ok())
+            .collect();
+        let mut keep = keep.into_iter();
+        self.words.retain(|_| keep.next().unwrap());
+        self.nwords = words.len() as i32;
+
+        *idx = words;
+        idx.extend(ngrams);
+        self.init_word2int();
+        self.init_ngrams();
+    }
+
+    pub fn nwords(&self) -> i32 {
+        self.nwords
+    }
+
+    pub fn nlabels(&self) -> i32 {
+        self.nlabels
+    }
+
+    /// number of tokens in the training data.
+    pub fn ntokens(&self) -> i64 {
+        self.ntokens
+    }
+
+    pub fn is_pruned(&self) -> bool {
+        self.pruneidx_size >= 0
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.words
+    }
+
+    pub fn get_id(&self, word: &str) -> Option<i32> {
+        self.word2int.get(word).cloned()
+    }
+
+    /// the label with the given label index (0 is the first label, not the first entry).
+    pub fn get_label(&self, lid: i32) -> &str {
+        &self.words[(self.nwords + lid) as usize].word
+    }
+
+    /// counts of words or of labels, in id order.
+    pub fn counts(&self, entry_type: EntryType) -> Vec<i64> {
+        self.words.iter().filter(|e| e.entry_type == entry_type).map(|e| e.count).collect()
+    }
+
+    fn get_type(&self, word: &str) -> EntryType {
+        if word.starts_with(&self.args.label) { EntryType::Label } else { EntryType::Word }
+    }
+
+    /// input matrix row of a hashed bucket, following the pruning remap if any; None if the
+    /// bucket was pruned.
+    fn bucket_row(&self, id: i32) -> Option<i32> {
+        if self.pruneidx_size == 0 || id < 0 {
+            return None;
+        }
+        let id = if self.pruneidx_size > 0 {
+            *self.pruneidx.get(&id)?
+        } else {
+            id
+        };
+        Some(self.nwords + id)
+    }
+
+    /// adds the input matrix row for a hashed bucket, following the pruning remap if any.
+    fn push_hash(&self, hashes: &mut Vec<i32>, id: i32) {
+        if let Some(row) = self.bucket_row(id) {
+            hashes.push(row);
+        }
+    }
+
+    /// n-gram buckets of a word that is already wrapped in BOW and EOW.
+    fn compute_subwords(&self, word: &str, ngrams: &mut Vec<i32>) {
+        self.for_each_ngram(word, |_, h| self.push_hash(ngrams, h as i32));
+    }
+
+    /// calls f with every character n-gram of a word wrapped in BOW and EOW and its bucket.
+    fn for_each_ngram<F: FnMut(&str, u32)>(&self, word: &str, mut f: F) {
+        let b = word.as_bytes();
+        for i in 0..b.len() {
+            if (b[i] & 0xC0) == 0x80 {
+                continue;
+            }
+            let mut j = i;
+            let mut n = 1;
+            while j < b.len() && n <= self.args.maxn {
+                j += 1;
+                while j < b.len() && (b[j] & 0xC0) == 0x80 {
+                    j += 1;
+                }
+                if n >= self.args.minn && !(n == 1 && (i == 0 || j == b.len())) {
+                    f(&word[i..j], hash(&word[i..j]) % self.args.bucket as u32);
+                }
+                n += 1;
+            }
+        }
+    }
+
+    fn init_ngrams(&mut self) {
+        for i in 0..self.words.len() {
+            let mut subwords = vec![i as i32];
+            if self.words[i].word != EOS {
+                let w = s_bow(&self.words[i].word);
+                self.compute_subwords(&w, &mut subwords);
+            }
+            self.words[i].subwords = subwords;
+        }
+    }
+
+    /// input matrix rows that make up a word, in or out of vocabulary.
+    pub fn get_subwords(&self, word: &str) -> Vec<i32> {
+        if let Some(i) = self.get_id(word) {
+            return self.words[i as usize].subwords.clone();
+        }
+        let mut ngrams = Vec::new();
+        if word != EOS {
+            self.compute_subwords(&s_bow(word), &mut ngrams);
+        }
+        ngrams
+    }
+
+    /// Character n-grams of a word with their buckets and input matrix rows, as fastText's
+    /// print-ngrams lists them. N-grams whose bucket was pruned have no row.
+    pub fn get_ngrams(&self, word: &str) -> Vec<(String, u32, Option<i32>)> {
+        let mut out = Vec::new();
+        if word != EOS {
+            self.for_each_ngram(&s_bow(word), |ngram, h| out.push((ngram.to_string(), h, self.bucket_row(h as i32))));
+        }
+        out
+    }
+
+    fn add_subwords(&self, line: &mut Vec<i32>, token: &str, wid: Option<i32>) {
+        match wid {
+            None => if token != EOS {
+                self.compute_subwords(&s_bow(token), line);
+            },
+            Some(wid) => if self.args.maxn <= 0 {
+                line.push(wid);
+            } else {
+                line.extend_from_slice(&self.words[wid as usize].subwords);
+            },
+        }
+    }
+
+    fn add_word_ngrams(&self, line: &mut Vec<i32>, hashes: &[i32], n: i32) {
+        for i in 0..hashes.len() {
+            // the C++ code widens the signed 32 bit hashes, so sign extension is intended here.
+            let mut h = hashes[i] as i64 as u64;
+            for (j, &hj) in hashes.iter().enumerate().skip(i + 1) {
+                if j >= i + n as usize {
+                    break;
+                }
+                h = h.wrapping_mul(116049371).wrapping_add(hj as i64 as u64);
+                self.push_hash(line, (h % self.args.bucket as u64) as i32);
+            }
+        }
+    }
+
+    /// Input rows and label indices for one line of text, as fastText's getLine computes them
+    /// for supervised models.
+    pub fn get_line(&self, line: &str) -> (Vec<i32>, Vec<i32>) {
+        let mut words = Vec::new();
+        let mut labels = Vec::new();
+        let mut hashes = Vec::new();
+        for token in tokenize(line) {
+            let h = hash(token);
+            let wid = self.get_id(token);
+            let entry_type = match wid {
+                Some(i) => self.words[i as usize].entry_type,
+                None => self.get_type(token),
+            };
+            match entry_type {
+                EntryType::Word => {
+                    self.add_subwords(&mut words, token, wid);
+                    hashes.push(h as i32);
+                }
+                EntryType::Label => if let Some(i) = wid {
+                    labels.push(i - self.nwords);
+                },
+            }
+        }
+        self.add_word_ngrams(&mut words, &hashes, self.args.word_ngrams);
+        (words, labels)
+    }
+
+    /// ids of the known words of a line, EOS included, for unsupervised training.
+    pub fn get_word_ids(&self, line: &str) -> Vec<i32> {
+        tokenize(line).into_iter()
+            .filter_map(|t| self.get_id(t))
+            .filter(|&i| i < self.nwords)
+            .collect()
+    }
+}
+
+fn s_bow(word: &str) -> String {
+    format!("{}{}{}", BOW, word, EOW)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict(words: &[&str], minn: i32, maxn: i32) -> Dictionary {
+        let args = TrainArgs { minn, maxn, bucket: 1000, ..TrainArgs::default() };
+        let words = words.iter().map(|w| Entry {
+            word: w.to_string(),
+            count: 1,
+            entry_type: if w.starts_with("__label__") { EntryType::Label } else { EntryType::Word },
+            subwords: Vec::new(),
+        }).collect();
+        Dictionary::new(&args, words, 0)
+    }
+
+    #[test]
+    fn test_hash() {
+        // reference values from fastText's Dictionary::hash
+        assert_eq!(hash(""), 2166136261);
+        assert_eq!(hash("a"), 3826002220);
+        assert_eq!(hash("</s>"), 3617362777);
+        // bytes above 0x7f are sign-extended
+        assert_eq!(hash("é"), 1023043777);
+    }
+
+    #[test]
+    fn test_subwords() {
+        let d = dict(&["</s>", "where"], 3, 3);
+        // <wh whe her ere re>
+        assert_eq!(d.get_subwords("where").len(), 6);
+        assert_eq!(d.get_subwords("where")[0], 1);
+        assert_eq!(d.get_subwords("</s>"), vec![0]);
+        // out of vocabulary words only get n-grams: <th the hem em>
+        assert_eq!(d.get_subwords("them").len(), 4);
+        // multi-byte characters count as one character
+        assert_eq!(d.get_subwords("éé").len(), 2);
+
+        let ngrams = d.get_ngrams("where");
+        let names: Vec<_> = ngrams.iter().map(|n| n.0.as_str()).collect();
+        assert_eq!(names, vec!["<wh", "whe", "her", "ere", "re>"]);
+        assert_eq!(ngrams[0].1, hash("<wh") % 1000);
+        assert_eq!(ngrams[0].2, Some(2 + ngrams[0].1 as i32));
+        assert!(d.get_ngrams("</s>").is_empty());
+    }
+
+    #[test]
+    fn test_build() {
+        let args = TrainArgs { min_count: 2, maxn: 0, ..TrainArgs::default() };
+        let corpus = "__label__a cheese sauce\n__label__b sauce pie\n__label__a cheese sauce";
+        let d = Dictionary::build(&args, corpus.as_bytes()).unwrap();
+        let words: Vec<_> = d.entries().iter().map(|e| (e.word.as_str(), e.count)).collect();
+        assert_eq!(words, vec![("sauce", 3), ("</s>", 3), ("cheese", 2), ("__label__a", 2), ("__label__b", 1)]);
+        assert_eq!((d.nwords(), d.nlabels(), d.ntokens()), (3, 2, 12));
+
+        let args = TrainArgs { min_count: 5, ..args };
+        assert!(Dictionary::build(&args, corpus.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_extend() {
+        let args = TrainArgs { min_count: 1, maxn: 0, ..TrainArgs::default() };
+        let d = Dictionary::build(&args, "__label__a cheese sauce\n__label__b sauce".as_bytes()).unwrap();
+        let (entries, ntokens) = count_entries(&args, "__label__c pie sauce pie\n".as_bytes()).unwrap();
+        let e = d.extend(entries, ntokens);
+        let words: Vec<_> = e.entries().iter().map(|e| (e.word.as_str(), e.count)).collect();
+        assert_eq!(words, vec![("sauce", 2), ("</s>", 2), ("cheese", 1), ("pie", 2),
+                               ("__label__a", 1), ("__label__b", 1), ("__label__c", 1)]);
+        assert_eq!((e.nwords(), e.nlabels(), e.ntokens()), (4, 3, 12));
+        assert_eq!(e.get_line("__label__c pie").1, vec![2]);
+    }
+
+    #[test]
+    fn test_get_line() {
+        let d = dict(&["</s>", "cheese", "sauce", "__label__food"], 0, 0);
+        let (words, labels) = d.get_line("__label__food cheese sauce unknown");
+        assert_eq!(words, vec![1, 2, 0]);
+        assert_eq!(labels, vec![0]);
+        assert_eq!(d.get_label(0), "__label__food");
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut d = dict(&["</s>", "cheese", "sauce", "potato", "__label__food"], 3, 3);
+        let potato = d.get_subwords("potato");
+        // keep </s>, potato and one n-gram of potato
+        let mut idx = vec![potato[2], 3, 0];
+        d.prune(&mut idx);
+        assert_eq!(idx, vec![0, 3, potato[2]]);
+        assert!(d.is_pruned());
+        assert_eq!(d.nwords(), 2);
+        assert_eq!(d.get_id("potato"), Some(1));
+        assert_eq!(d.get_id("cheese"), None);
+        assert_eq!(d.get_label(0), "__label__food");
+        // potato and its only retained n-gram, which now follows the words
+        assert_eq!(d.get_subwords("potato"), vec![1, 2]);
+        let mut b = Vec::new();
+        d.write(&mut b).unwrap();
+        let r = Dictionary::read(&mut &b[..], &d.args).unwrap();
+        assert_eq!(r.get_subwords("potato"), vec![1, 2]);
+        assert_eq!(r.words, d.words);
+    }
+}
@@ -0,0 +1,285 @@
+//! fastText itself, linked into the crate with the `ffi` feature: `build.rs` compiles the
+//! vendored C++ sources with a small C shim (`ffi/fasttext_shim.h`), wrapped here in a safe
+//! `Model` that is freed when dropped. Nothing is run as a subprocess.
+//!
+//! ```no_run
+//! use fast_text::ffi::Model;
+//! use fast_text::TrainArgs;
+//!
+//! let model = Model::train(&TrainArgs::supervised(), "sample_text.txt", "sample").unwrap();
+//! let labels = model.predict("our military athletes", 2).unwrap();
+//! let same = Model::load("sample.bin").unwrap();
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::raw::{c_char, c_float, c_int, c_void};
+use std::path::Path;
+use std::ptr::{self, NonNull};
+
+use {QuantizeArgs, TrainArgs};
+
+/// the declarations of fasttext_shim.h.
+#[allow(non_camel_case_types)]
+mod sys {
+    use std::os::raw::{c_char, c_float, c_int, c_void};
+
+    #[repr(C)]
+    pub struct ft_model {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    pub struct ft_train_args {
+        pub input: *const c_char,
+        pub output: *const c_char,
+        pub lr: f64,
+        pub lr_update_rate: c_int,
+        pub dim: c_int,
+        pub ws: c_int,
+        pub epoch: c_int,
+        pub min_count: c_int,
+        pub min_count_label: c_int,
+        pub neg: c_int,
+        pub word_ngrams: c_int,
+        pub loss: c_int,
+        pub model: c_int,
+        pub bucket: c_int,
+        pub minn: c_int,
+        pub maxn: c_int,
+        pub thread: c_int,
+        pub t: f64,
+        pub label: *const c_char,
+    }
+
+    #[repr(C)]
+    pub struct ft_quantize_args {
+        pub input: *const c_char,
+        pub output: *const c_char,
+        pub cutoff: usize,
+        pub retrain: c_int,
+        pub qnorm: c_int,
+        pub qout: c_int,
+        pub dsub: usize,
+    }
+
+    pub type ft_pair_cb = extern "C" fn(ctx: *mut c_void, name: *const c_char, score: c_float);
+
+    extern "C" {
+        pub fn ft_string_free(s: *mut c_char);
+        pub fn ft_load(path: *const c_char, err: *mut *mut c_char) -> *mut ft_model;
+        pub fn ft_free(model: *mut ft_model);
+        pub fn ft_dim(model: *const ft_model) -> c_int;
+        pub fn ft_predict(model: *mut ft_model, text: *const c_char, k: c_int, cb: ft_pair_cb, ctx: *mut c_void,
+                          err: *mut *mut c_char) -> c_int;
+        pub fn ft_word_vector(model: *mut ft_model, word: *const c_char, out: *mut c_float, err: *mut *mut c_char) -> c_int;
+        pub fn ft_sentence_vector(model: *mut ft_model, text: *const c_char, out: *mut c_float,
+                                  err: *mut *mut c_char) -> c_int;
+        pub fn ft_nearest_neighbors(model: *mut ft_model, word: *const c_char, k: c_int, cb: ft_pair_cb,
+                                    ctx: *mut c_void, err: *mut *mut c_char) -> c_int;
+        pub fn ft_train(args: *const ft_train_args, err: *mut *mut c_char) -> *mut ft_model;
+        pub fn ft_quantize(args: *const ft_quantize_args, err: *mut *mut c_char) -> *mut ft_model;
+    }
+}
+
+/// A fastText model in this process's memory.
+///
+/// The C++ object is owned by the `Model` and freed when it is dropped. Results are copied out
+/// of it, so they outlive it. fastText caches some state on the first `nearest_neighbors`
+/// call, so a `Model` can be moved to another thread but not shared between threads.
+pub struct Model {
+    raw: NonNull<sys::ft_model>,
+}
+
+// the model is only reachable through its owner, and fastText keeps no thread-local state.
+unsafe impl Send for Model {}
+
+impl Drop for Model {
+    fn drop(&mut self) {
+        unsafe { sys::ft_free(self.raw.as_ptr()) }
+    }
+}
+
+fn cstring(s: &str) -> io::Result<CString> {
+    CString::new(s).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "fastText arguments cannot contain NUL bytes"))
+}
+
+fn path_cstring(p: &Path) -> io::Result<CString> {
+    cstring(p.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "fastText paths must be UTF-8"))?)
+}
+
+/// the error a shim call reported, releasing its message.
+fn take_error(err: *mut c_char) -> io::Error {
+    if err.is_null() {
+        return io::Error::other("fastText failed");
+    }
+    let msg = unsafe { CStr::from_ptr(err) }.to_string_lossy().into_owned();
+    unsafe { sys::ft_string_free(err) };
+    io::Error::other(msg)
+}
+
+fn check(status: c_int, err: *mut c_char) -> io::Result<()> {
+    if status == 0 { Ok(()) } else { Err(take_error(err)) }
+}
+
+fn owned(raw: *mut sys::ft_model, err: *mut c_char) -> io::Result<Model> {
+    NonNull::new(raw).map(|raw| Model { raw }).ok_or_else(|| take_error(err))
+}
+
+/// collects the pairs of a result into the `Vec<(String, f32)>` behind ctx.
+extern "C" fn push_pair(ctx: *mut c_void, name: *const c_char, score: c_float) {
+    let out = unsafe { &mut *(ctx as *mut Vec<(String, f32)>) };
+    out.push((unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned(), score));
+}
+
+impl Model {
+    /// Loads a `.bin` or `.ftz` model.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Model> {
+        let path = path_cstring(path.as_ref())?;
+        let mut err = ptr::null_mut();
+        owned(unsafe { sys::ft_load(path.as_ptr(), &mut err) }, err)
+    }
+
+    /// Trains a model on `input` like the fastText binary and saves it to `<output>.bin`.
+    pub fn train<P: AsRef<Path>>(args: &TrainArgs, input: P, output: &str) -> io::Result<Model> {
+        let (input, out, label) = (path_cstring(input.as_ref())?, cstring(output)?, cstring(&args.label)?);
+        let a = sys::ft_train_args {
+            input: input.as_ptr(),
+            output: out.as_ptr(),
+            lr: args.lr,
+            lr_update_rate: args.lr_update_rate,
+            dim: args.dim,
+            ws: args.ws,
+            epoch: args.epoch,
+            min_count: args.min_count,
+            min_count_label: args.min_count_label,
+            neg: args.neg,
+            word_ngrams: args.word_ngrams,
+            loss: args.loss.to_i32(),
+            model: args.model.to_i32(),
+            bucket: args.bucket,
+            minn: args.minn,
+            maxn: args.maxn,
+            thread: args.thread,
+            t: args.t,
+            label: label.as_ptr(),
+        };
+        let mut err = ptr::null_mut();
+        owned(unsafe { sys::ft_train(&a, &mut err) }, err)
+    }
+
+    /// Quantizes the supervised model `<output>.bin` and saves the result to `<output>.ftz`.
+    /// `retrain` needs the training data as `input`.
+    pub fn quantize(output: &str, qargs: &QuantizeArgs, input: Option<&Path>) -> io::Result<Model> {
+        if qargs.retrain && input.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "retraining a quantized model needs the training data"));
+        }
+        let input = match input {
+            Some(p) => Some(path_cstring(p)?),
+            None => None,
+        };
+        let out = cstring(output)?;
+        let a = sys::ft_quantize_args {
+            input: input.as_ref().map_or(ptr::null(), |i| i.as_ptr()),
+            output: out.as_ptr(),
+            cutoff: qargs.cutoff,
+            retrain: qargs.retrain as c_int,
+            qnorm: qargs.qnorm as c_int,
+            qout: qargs.qout as c_int,
+            dsub: qargs.dsub,
+        };
+        let mut err = ptr::null_mut();
+        owned(unsafe { sys::ft_quantize(&a, &mut err) }, err)
+    }
+
+    /// Size of the vectors.
+    pub fn dim(&self) -> usize {
+        unsafe { sys::ft_dim(self.raw.as_ptr()) as usize }
+    }
+
+    /// Top k labels for a line of text with their probabilities, most likely first.
+    pub fn predict(&self, text: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        let text = cstring(text)?;
+        let mut out: Vec<(String, f32)> = Vec::new();
+        let mut err = ptr::null_mut();
+        let status = unsafe {
+            sys::ft_predict(self.raw.as_ptr(), text.as_ptr(), k as c_int, push_pair,
+                            &mut out as *mut _ as *mut c_void, &mut err)
+        };
+        check(status, err).map(|_| out)
+    }
+
+    /// Vector for a word, built from its character n-grams if it is out of vocabulary.
+    pub fn word_vector(&self, word: &str) -> io::Result<Vec<f32>> {
+        let word = cstring(word)?;
+        let mut v = vec![0.0; self.dim()];
+        let mut err = ptr::null_mut();
+        let status = unsafe { sys::ft_word_vector(self.raw.as_ptr(), word.as_ptr(), v.as_mut_ptr(), &mut err) };
+        check(status, err).map(|_| v)
+    }
+
+    /// Vector for a line of text.
+    pub fn sentence_vector(&self, text: &str) -> io::Result<Vec<f32>> {
+        let text = cstring(text)?;
+        let mut v = vec![0.0; self.dim()];
+        let mut err = ptr::null_mut();
+        let status = unsafe { sys::ft_sentence_vector(self.raw.as_ptr(), text.as_ptr(), v.as_mut_ptr(), &mut err) };
+        check(status, err).map(|_| v)
+    }
+
+    /// The k words closest to a word, most similar first, leaving out the word itself.
+    pub fn nearest_neighbors(&self, word: &str, k: usize) -> io::Result<Vec<(String, f32)>> {
+        let word = cstring(word)?;
+        let mut out: Vec<(String, f32)> = Vec::new();
+        let mut err = ptr::null_mut();
+        let status = unsafe {
+            sys::ft_nearest_neighbors(self.raw.as_ptr(), word.as_ptr(), k as c_int, push_pair,
+                                      &mut out as *mut _ as *mut c_void, &mut err)
+        };
+        check(status, err).map(|_| out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use FastTextModel;
+
+    #[test]
+    fn test_ffi_model() {
+        let output = env::temp_dir().join(format!("fast_text_{}_ffi", process::id()));
+        let output = output.to_str().unwrap();
+        let args = TrainArgs { dim: 10, thread: 1, bucket: 1000, ..TrainArgs::supervised() };
+        let model = Model::train(&args, "sample_text.txt", output).unwrap();
+        assert_eq!(model.dim(), 10);
+        assert_eq!(model.predict("our military athletes", 2).unwrap().len(), 2);
+        assert_eq!(model.sentence_vector("our military athletes").unwrap().len(), 10);
+        let nn = model.nearest_neighbors("military", 3).unwrap();
+        assert!(nn.len() == 3 && nn.iter().all(|n| n.0 != "military"));
+
+        // fastText's model files are readable natively, and give the same vectors
+        let bin = output.to_string() + ".bin";
+        let native = FastTextModel::load(&bin).unwrap();
+        assert_eq!(Model::load(&bin).unwrap().word_vector("military").unwrap(), native.word_vector("military"));
+        assert!(Model::load("missing.bin").is_err());
+        assert!(model.predict("nul\0byte", 1).is_err());
+
+        let retrain = QuantizeArgs { cutoff: 100, retrain: true, ..QuantizeArgs::default() };
+        assert_eq!(Model::quantize(output, &retrain, None).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        // the shim refuses too, rather than having fastText read the data from standard input
+        let out = cstring(output).unwrap();
+        let a = sys::ft_quantize_args {
+            input: ptr::null(), output: out.as_ptr(), cutoff: 100, retrain: 1, qnorm: 0, qout: 0, dsub: 2,
+        };
+        let mut err = ptr::null_mut();
+        let e = owned(unsafe { sys::ft_quantize(&a, &mut err) }, err).err().unwrap();
+        assert!(e.to_string().contains("training data"));
+        Model::quantize(output, &QuantizeArgs::default(), None).unwrap();
+        let ftz = output.to_string() + ".ftz";
+        assert!(FastTextModel::load(&ftz).unwrap().is_quantized());
+        fs::remove_file(&bin).unwrap();
+        fs::remove_file(&ftz).unwrap();
+    }
+}
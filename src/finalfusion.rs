@@ -0,0 +1,393 @@
+//! Conversion to and from finalfusion, the embedding format of the Rust NLP ecosystem.
+//!
+//! Models with character n-grams are stored with a fastText subword vocabulary, so the words,
+//! the number of buckets and the n-gram range are kept and out-of-vocabulary words get the
+//! same vectors in both formats. As finalfusion expects, the storage holds the precomputed
+//! vector of every word, normalized, followed by the n-gram bucket rows, and the norms are
+//! stored in a separate chunk. Other models are stored with a simple vocabulary.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use args::TrainArgs;
+use bin_io::{invalid, read_bytes, read_f32s, write_f32s};
+use dictionary::{Dictionary, Entry, EntryType};
+use matrix::Matrix;
+use model::FastTextModel;
+
+const MAGIC: &[u8; 4] = b"FiFu";
+const VERSION: u32 = 0;
+
+// chunk identifiers
+const SIMPLE_VOCAB: u32 = 1;
+const NDARRAY: u32 = 2;
+const BUCKET_SUBWORD_VOCAB: u32 = 3;
+const QUANTIZED_ARRAY: u32 = 4;
+const METADATA: u32 = 5;
+const NDNORMS: u32 = 6;
+const FASTTEXT_SUBWORD_VOCAB: u32 = 7;
+
+/// finalfusion's type id for f32.
+const F32_TYPE: u32 = 10;
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+/// counts the bytes written, as arrays are padded to a multiple of 4 bytes from the start of
+/// the file.
+struct Counting<W: Write> {
+    writer: W,
+    pos: u64,
+}
+
+impl<W: Write> Counting<W> {
+    fn u32(&mut self, v: u32) -> io::Result<()> {
+        self.write_all(&v.to_le_bytes())
+    }
+
+    fn u64(&mut self, v: u64) -> io::Result<()> {
+        self.write_all(&v.to_le_bytes())
+    }
+
+    /// padding needed before f32 data once `header` more bytes are written. Like finalfusion,
+    /// this pads aligned data with 4 bytes rather than none: its readers always skip 1 to 4.
+    fn padding(&self, header: u64) -> u64 {
+        4 - (self.pos + header) % 4
+    }
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes the input vectors of a model as finalfusion embeddings. Models whose vocabulary was
+/// pruned by quantization cannot be converted, as finalfusion has no equivalent.
+pub fn to_finalfusion<P: AsRef<Path>>(model: &FastTextModel, path: P) -> io::Result<()> {
+    let dict = model.dictionary();
+    if dict.is_pruned() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "models with a pruned vocabulary cannot be converted"));
+    }
+    let args = model.args();
+    let subwords = args.maxn > 0 && args.bucket > 0;
+    let words = &dict.entries()[..dict.nwords() as usize];
+    let buckets = if subwords { args.bucket as usize } else { 0 };
+    let dim = model.dim();
+
+    let mut w = Counting { writer: BufWriter::new(File::create(path)?), pos: 0 };
+    w.write_all(MAGIC)?;
+    w.u32(VERSION)?;
+    w.u32(3)?;
+    let vocab_id = if subwords { FASTTEXT_SUBWORD_VOCAB } else { SIMPLE_VOCAB };
+    for &id in &[vocab_id, NDARRAY, NDNORMS] {
+        w.u32(id)?;
+    }
+
+    let word_bytes: u64 = words.iter().map(|e| 4 + e.word.len() as u64).sum();
+    w.u32(vocab_id)?;
+    if subwords {
+        w.u64(8 + 12 + word_bytes)?;
+        w.u64(words.len() as u64)?;
+        w.u32(args.minn as u32)?;
+        w.u32(args.maxn as u32)?;
+        w.u32(buckets as u32)?;
+    } else {
+        w.u64(8 + word_bytes)?;
+        w.u64(words.len() as u64)?;
+    }
+    for e in words {
+        w.u32(e.word.len() as u32)?;
+        w.write_all(e.word.as_bytes())?;
+    }
+
+    let mut norms = Vec::with_capacity(words.len());
+    w.u32(NDARRAY)?;
+    let padding = w.padding(8 + 8 + 4 + 4);
+    w.u64(16 + padding + ((words.len() + buckets) * dim * 4) as u64)?;
+    w.u64((words.len() + buckets) as u64)?;
+    w.u32(dim as u32)?;
+    w.u32(F32_TYPE)?;
+    w.write_all(&vec![0; padding as usize])?;
+    for e in words {
+        let mut v = model.word_vector(&e.word);
+        let n = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if n > 0.0 {
+            for x in v.iter_mut() {
+                *x /= n;
+            }
+        }
+        norms.push(n);
+        write_f32s(&mut w, &v)?;
+    }
+    for i in 0..buckets {
+        write_f32s(&mut w, &model.input_row(words.len() + i))?;
+    }
+
+    w.u32(NDNORMS)?;
+    let padding = w.padding(8 + 8 + 4);
+    w.u64(12 + padding + norms.len() as u64 * 4)?;
+    w.u64(norms.len() as u64)?;
+    w.u32(F32_TYPE)?;
+    w.write_all(&vec![0; padding as usize])?;
+    write_f32s(&mut w, &norms)?;
+    w.flush()
+}
+
+fn read_words<R: Read>(r: &mut R, n: u64) -> io::Result<Vec<String>> {
+    (0..n).map(|_| {
+        let len = read_u32(r)? as usize;
+        String::from_utf8(read_bytes(r, len)?).map_err(|_| invalid("finalfusion word is not valid UTF-8"))
+    }).collect()
+}
+
+/// Reads finalfusion embeddings with a simple or fastText subword vocabulary into an
+/// unsupervised model with the same word and out-of-vocabulary vectors.
+pub fn from_finalfusion<P: AsRef<Path>>(path: P) -> io::Result<FastTextModel> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a finalfusion file"));
+    }
+    if read_u32(&mut r)? != VERSION {
+        return Err(invalid("unsupported finalfusion version"));
+    }
+    let nchunks = read_u32(&mut r)?;
+    for _ in 0..nchunks {
+        read_u32(&mut r)?;
+    }
+
+    let mut args = TrainArgs { minn: 0, maxn: 0, bucket: 0, ..TrainArgs::default() };
+    let mut words = None;
+    let mut storage = None;
+    let mut norms = None;
+    for _ in 0..nchunks {
+        let id = read_u32(&mut r)?;
+        let len = read_u64(&mut r)?;
+        match id {
+            SIMPLE_VOCAB => {
+                let n = read_u64(&mut r)?;
+                words = Some(read_words(&mut r, n)?);
+            }
+            FASTTEXT_SUBWORD_VOCAB => {
+                let n = read_u64(&mut r)?;
+                args.minn = read_u32(&mut r)? as i32;
+                args.maxn = read_u32(&mut r)? as i32;
+                args.bucket = read_u32(&mut r)? as i32;
+                words = Some(read_words(&mut r, n)?);
+            }
+            NDARRAY => {
+                let m = read_u64(&mut r)? as usize;
+                let n = read_u32(&mut r)? as usize;
+                if read_u32(&mut r)? != F32_TYPE {
+                    return Err(invalid("finalfusion storage is not f32"));
+                }
+                let padding = len.checked_sub(16 + (m * n * 4) as u64).ok_or_else(|| invalid("finalfusion storage is truncated"))?;
+                read_bytes(&mut r, padding as usize)?;
+                storage = Some(Matrix::new(m, n, read_f32s(&mut r, m * n)?));
+            }
+            NDNORMS => {
+                let n = read_u64(&mut r)? as usize;
+                if read_u32(&mut r)? != F32_TYPE {
+                    return Err(invalid("finalfusion norms are not f32"));
+                }
+                let padding = len.checked_sub(12 + (n * 4) as u64).ok_or_else(|| invalid("finalfusion norms are truncated"))?;
+                read_bytes(&mut r, padding as usize)?;
+                norms = Some(read_f32s(&mut r, n)?);
+            }
+            METADATA => {
+                read_bytes(&mut r, len as usize)?;
+            }
+            BUCKET_SUBWORD_VOCAB | QUANTIZED_ARRAY => {
+                return Err(invalid("only simple and fastText vocabularies with f32 storage are supported"));
+            }
+            _ => return Err(invalid("unknown finalfusion chunk")),
+        }
+    }
+
+    let words = words.ok_or_else(|| invalid("finalfusion file has no vocabulary"))?;
+    let storage = storage.ok_or_else(|| invalid("finalfusion file has no storage"))?;
+    if storage.rows() != words.len() + args.bucket as usize {
+        return Err(invalid("finalfusion storage does not match the vocabulary"));
+    }
+    if norms.as_ref().is_some_and(|n| n.len() != words.len()) {
+        return Err(invalid("finalfusion norms do not match the vocabulary"));
+    }
+    args.dim = storage.cols() as i32;
+
+    // undo the precomputation: fastText averages a word's own row with its n-gram rows, which
+    // all come after the words
+    let entries = words.iter()
+        .map(|w| Entry { word: w.clone(), count: 1, entry_type: EntryType::Word, subwords: Vec::new() })
+        .collect();
+    let dict = Dictionary::new(&args, entries, words.len() as i64);
+    let mut storage = storage;
+    for (i, e) in dict.entries().iter().enumerate() {
+        let mut v = storage.row(i).to_vec();
+        let a = norms.as_ref().map_or(1.0, |n| n[i]) * e.subwords.len() as f32;
+        for x in v.iter_mut() {
+            *x *= a;
+        }
+        for &s in &e.subwords[1..] {
+            storage.add_row_to(&mut v, s as usize, -1.0);
+        }
+        storage.row_mut(i).copy_from_slice(&v);
+    }
+    FastTextModel::from_embeddings(args, words, storage)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate finalfusion as ff;
+    extern crate ndarray;
+
+    use std::{env, fs, process};
+
+    use self::ff::compat::fasttext::FastTextIndexer;
+    use self::ff::embeddings::Embeddings;
+    use self::ff::io::{ReadEmbeddings, WriteEmbeddings};
+    use self::ff::norms::NdNorms;
+    use self::ff::storage::{NdArray, StorageWrap};
+    use self::ff::subword::BucketIndexer;
+    use self::ff::vocab::{FastTextSubwordVocab, VocabWrap};
+    use self::ndarray::{Array1, Array2};
+    use args::TrainArgs;
+    use rng::MinstdRand;
+    use super::*;
+
+    fn model(minn: i32, maxn: i32, bucket: usize) -> FastTextModel {
+        let args = TrainArgs { dim: 3, minn, maxn, bucket: bucket as i32, ..TrainArgs::default() };
+        let words: Vec<String> = vec!["</s>", "cheese", "sauce", "pie"].into_iter().map(String::from).collect();
+        let mut rng = MinstdRand::new(3);
+        let m = words.len() + bucket;
+        let data = (0..m * 3).map(|_| rng.uniform() as f32 - 0.5).collect();
+        FastTextModel::from_embeddings(args, words, Matrix::new(m, 3, data)).unwrap()
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &(minn, maxn, bucket) in &[(2, 4, 50), (0, 0, 0)] {
+            let m = model(minn, maxn, bucket);
+            let path = env::temp_dir().join(format!("fast_text_{}_{}.fifu", process::id(), bucket));
+            to_finalfusion(&m, &path).unwrap();
+            let b = fs::read(&path).unwrap();
+            let loaded = from_finalfusion(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(&b[..4], MAGIC);
+            let args = loaded.args();
+            assert_eq!((args.dim, args.minn, args.maxn, args.bucket), (3, minn, maxn, bucket as i32));
+            let words: Vec<_> = loaded.dictionary().entries().iter().map(|e| e.word.clone()).collect();
+            assert_eq!(words, vec!["</s>", "cheese", "sauce", "pie"]);
+            for w in &["</s>", "cheese", "pie", "cheesy", "x"] {
+                assert_close(&loaded.word_vector(w), &m.word_vector(w));
+            }
+        }
+    }
+
+    fn unit(v: &[f32]) -> Vec<f32> {
+        let n = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        v.iter().map(|x| x / n).collect()
+    }
+
+    // vocabularies a byte apart, so that the arrays start at different offsets
+    const WORDS: [&[&str]; 2] = [&["</s>", "cheese", "pie"], &["</s>", "cheese", "pies"]];
+
+    #[test]
+    fn test_read_by_finalfusion() {
+        for words in &WORDS {
+            let args = TrainArgs { dim: 3, minn: 2, maxn: 4, bucket: 50, ..TrainArgs::default() };
+            let mut rng = MinstdRand::new(5);
+            let rows = words.len() + 50;
+            let data = (0..rows * 3).map(|_| rng.uniform() as f32 - 0.5).collect();
+            let words = words.iter().map(|w| w.to_string()).collect();
+            let m = FastTextModel::from_embeddings(args, words, Matrix::new(rows, 3, data)).unwrap();
+            let path = env::temp_dir().join(format!("fast_text_{}_to_ff.fifu", process::id()));
+            to_finalfusion(&m, &path).unwrap();
+            let embeds: Embeddings<VocabWrap, StorageWrap> =
+                Embeddings::read_embeddings(&mut BufReader::new(File::open(&path).unwrap())).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            let e = embeds.embedding_with_norm("cheese").unwrap();
+            let v: Vec<f32> = e.embedding.iter().map(|x| x * e.norm).collect();
+            assert_close(&v, &m.word_vector("cheese"));
+            // finalfusion sums the n-gram rows of unknown words and normalizes them
+            let e = embeds.embedding("cheesy").unwrap();
+            assert_close(e.as_slice().unwrap(), &unit(&m.word_vector("cheesy")));
+        }
+    }
+
+    #[test]
+    fn test_written_by_finalfusion() {
+        for words in &WORDS {
+            let mut rng = MinstdRand::new(7);
+            let rows = words.len() + 50;
+            let data = Array2::from_shape_fn((rows, 3), |_| rng.uniform() as f32 - 0.5);
+            let norms = Array1::from_shape_fn(words.len(), |i| i as f32 + 0.5);
+            let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+            let vocab = FastTextSubwordVocab::new(words, 2, 4, FastTextIndexer::new(50));
+            let embeds = Embeddings::new(None, vocab, NdArray::new(data), NdNorms::new(norms));
+            let path = env::temp_dir().join(format!("fast_text_{}_from_ff.fifu", process::id()));
+            embeds.write_embeddings(&mut BufWriter::new(File::create(&path).unwrap())).unwrap();
+            let m = from_finalfusion(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!((m.args().minn, m.args().maxn, m.args().bucket), (2, 4, 50));
+            let e = embeds.embedding_with_norm("cheese").unwrap();
+            let v: Vec<f32> = e.embedding.iter().map(|x| x * e.norm).collect();
+            assert_close(&m.word_vector("cheese"), &v);
+            let e = embeds.embedding("cheesy").unwrap();
+            assert_close(&unit(&m.word_vector("cheesy")), e.as_slice().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_storage_alignment() {
+        let path = env::temp_dir().join(format!("fast_text_{}_align.fifu", process::id()));
+        to_finalfusion(&model(2, 4, 10), &path).unwrap();
+        let b = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        // header, then the vocabulary chunk
+        let mut pos = 4 + 4 + 4 + 3 * 4;
+        let vocab_len = read_u64(&mut &b[pos + 4..]).unwrap() as usize;
+        pos += 12 + vocab_len;
+        assert_eq!(read_u32(&mut &b[pos..]).unwrap(), NDARRAY);
+        let array_len = read_u64(&mut &b[pos + 4..]).unwrap() as usize;
+        let data_start = pos + 12 + array_len - 14 * 3 * 4;
+        assert_eq!(data_start % 4, 0);
+        assert_eq!(read_u32(&mut &b[pos + 24..]).unwrap(), F32_TYPE);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = env::temp_dir().join(format!("fast_text_{}_bad.fifu", process::id()));
+        fs::write(&path, b"FiFu\x01\x00\x00\x00").unwrap();
+        assert!(from_finalfusion(&path).is_err());
+        fs::write(&path, b"not finalfusion").unwrap();
+        assert_eq!(from_finalfusion(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
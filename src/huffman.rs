@@ -0,0 +1,115 @@
+//! The Huffman tree used by hierarchical softmax.
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: i32,
+    left: i32,
+    right: i32,
+    count: i64,
+    binary: bool,
+}
+
+/// Binary tree over the output classes, built from their counts the way fastText's
+/// Model::buildTree does. Leaves are the classes (0..osz), inner nodes are rows of the output
+/// matrix (node - osz).
+#[derive(Debug, Clone)]
+pub struct HuffmanTree {
+    osz: usize,
+    tree: Vec<Node>,
+    /// for every leaf, the output rows of the inner nodes above it and the branch taken at
+    /// each, from the leaf up.
+    paths: Vec<Vec<(usize, bool)>>,
+}
+
+impl HuffmanTree {
+    pub fn new(counts: &[i64]) -> HuffmanTree {
+        let osz = counts.len();
+        let mut tree: Vec<Node> = (0..2 * osz.max(1) - 1).map(|_| Node {
+            parent: -1,
+            left: -1,
+            right: -1,
+            count: 1e15 as i64,
+            binary: false,
+        }).collect();
+        for (node, &c) in tree.iter_mut().zip(counts) {
+            node.count = c;
+        }
+        let mut leaf = osz as i32 - 1;
+        let mut node = osz;
+        for i in osz..(2 * osz).saturating_sub(1) {
+            let mut mini = [0; 2];
+            for m in mini.iter_mut() {
+                if leaf >= 0 && tree[leaf as usize].count < tree[node].count {
+                    *m = leaf as usize;
+                    leaf -= 1;
+                } else {
+                    *m = node;
+                    node += 1;
+                }
+            }
+            tree[i].left = mini[0] as i32;
+            tree[i].right = mini[1] as i32;
+            tree[i].count = tree[mini[0]].count + tree[mini[1]].count;
+            tree[mini[0]].parent = i as i32;
+            tree[mini[1]].parent = i as i32;
+            tree[mini[1]].binary = true;
+        }
+        let paths = (0..osz).map(|leaf| {
+            let mut path = Vec::new();
+            let mut j = leaf;
+            while tree[j].parent != -1 {
+                path.push((tree[j].parent as usize - osz, tree[j].binary));
+                j = tree[j].parent as usize;
+            }
+            path
+        }).collect();
+        HuffmanTree { osz, tree, paths }
+    }
+
+    /// output rows and branches on the way from the root's children to a leaf, leaf first, as
+    /// hierarchical softmax trains them.
+    pub fn path(&self, leaf: usize) -> &[(usize, bool)] {
+        &self.paths[leaf]
+    }
+
+    pub fn root(&self) -> usize {
+        self.tree.len() - 1
+    }
+
+    pub fn is_leaf(&self, node: usize) -> bool {
+        self.tree[node].left == -1 && self.tree[node].right == -1
+    }
+
+    pub fn left(&self, node: usize) -> usize {
+        self.tree[node].left as usize
+    }
+
+    pub fn right(&self, node: usize) -> usize {
+        self.tree[node].right as usize
+    }
+
+    /// output matrix row of an inner node.
+    pub fn output_row(&self, node: usize) -> usize {
+        node - self.osz
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree() {
+        // counts are sorted in decreasing order, as in a fastText dictionary
+        let t = HuffmanTree::new(&[10, 5, 3, 2]);
+        assert_eq!(t.root(), 6);
+        // the most frequent class hangs off the root, the rarest two share the lowest node
+        assert_eq!((t.left(6), t.right(6)), (5, 0));
+        assert_eq!((t.left(5), t.right(5)), (4, 1));
+        assert_eq!((t.left(4), t.right(4)), (3, 2));
+        assert!(t.is_leaf(0) && !t.is_leaf(6));
+        assert_eq!(t.output_row(6), 2);
+        assert_eq!(t.path(0), &[(2, true)]);
+        assert_eq!(t.path(3), &[(0, false), (1, false), (2, false)]);
+    }
+}
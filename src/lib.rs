@@ -1,12 +1,38 @@
 #[macro_use(c)]
 extern crate cute;
+extern crate memmap2;
+
+mod args;
+pub mod backend;
+mod bin_io;
+pub mod convert;
+mod dictionary;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod finalfusion;
+mod huffman;
+mod matrix;
+mod model;
+mod pca;
+pub mod projector;
+mod quantizer;
+mod rng;
+pub mod train;
+pub mod vec_file;
+
+pub use args::{LossName, ModelName, QuantizeArgs, TrainArgs};
+pub use model::{FastTextModel, LabelQuery};
+pub use train::{EpochMetrics, Trainer, TrainingSummary};
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs;
+use std::io;
 use std::process::{Command, Output, Stdio};
 
+use backend::CommandOutput;
 
-const VERSION: &'static str = "0.1.0";
+
+const VERSION: &str = "0.9.2";
 // fastText archive version to pull.
 
 const DEBUG: bool = true;
@@ -18,7 +44,7 @@ fn s(v: &str) -> String {
 /// Installs fastText from the archive on Facebook's github.
 pub fn install() -> Vec<Output> {
     let cmds = [
-        s("wget https://github.com/facebookresearch/fastText/archive/v0.1.0.zip"),
+        s("wget https://github.com/facebookresearch/fastText/archive/v") + VERSION + ".zip",
         s("unzip v") + VERSION + ".zip",
         s("cd fastText-") + VERSION + "; make",
         s("mv fastText-") + VERSION + "/fasttext .",
@@ -37,10 +63,10 @@ pub fn install() -> Vec<Output> {
      ]
 }
 
-/// runs a fastText command and, if it fails because fastText DNE, installs fastText and tries again.
-fn wrap_install(cmds: &str) -> Output {
-    let st = s("./fasttext ") + cmds;
-    run_cmd(&st)
+/// runs a fastText command with the current backend (see `backend`), which by default installs
+/// fastText first if it DNE.
+fn wrap_install(cmds: &str) -> CommandOutput {
+    backend::run(cmds, None)
 }
 
 /// Interface for fastText's supervised learning algorithm.
@@ -78,7 +104,7 @@ fn wrap_install(cmds: &str) -> Output {
 ///  -ws                 size of the context window [5]
 ///  -epoch              number of epochs [5]
 ///  -neg                number of negatives sampled [5]
-///  -loss               loss function {ns, hs, softmax} [softmax]
+///  -loss               loss function {ns, hs, softmax, one-vs-all} [softmax]
 ///  -thread             number of threads [12]
 ///  -pretrainedVectors  pretrained word vectors for supervised learning []
 ///  -saveOutput         whether output params should be saved [0]
@@ -123,7 +149,7 @@ pub fn supervised(args: &HashMap<&str, &str>) {
 ///  -ws                 size of the context window [5]
 ///  -epoch              number of epochs [5]
 ///  -neg                number of negatives sampled [5]
-///  -loss               loss function {ns, hs, softmax} [ns]
+///  -loss               loss function {ns, hs, softmax, one-vs-all} [ns]
 ///  -thread             number of threads [12]
 ///  -pretrainedVectors  pretrained word vectors for supervised learning []
 ///  -saveOutput         whether output params should be saved [0]
@@ -134,8 +160,138 @@ pub fn supervised(args: &HashMap<&str, &str>) {
 ///  -qnorm              quantizing the norm separately [0]
 ///  -qout               quantizing the classifier [0]
 ///  -dsub               size of each sub-vector [2]
-pub fn quantize(args: &HashMap<&str, &str>) {
-    gen_mod(s("quantize"), args);
+///
+/// fastText reads the model to compress from `<output>.bin` and writes the result to
+/// `<output>.ftz`. Returns the path of the compressed model and a report comparing it to the
+/// original. If `validation` is given, both models are evaluated on it (see `evaluate`) so the
+/// effect of the `cutoff` / `qnorm` / `qout` / `dsub` settings on accuracy can be checked.
+pub fn quantize(args: &HashMap<&str, &str>, validation: Option<&str>) -> io::Result<(String, QuantizationReport)> {
+    let output = match args.get("output") {
+        Some(o) => *o,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "quantize requires an output argument")),
+    };
+    if args.contains_key("retrain") && !args.contains_key("input") {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "retraining a quantized model needs the training data"));
+    }
+    let original = s(output) + ".bin";
+    let compressed = s(output) + ".ftz";
+
+    let before = match validation {
+        Some(v) => Some(evaluate(&original, v, 1)?),
+        None => None,
+    };
+
+    let st = gen_cmd(s("quantize"), args);
+    let o = wrap_install(&st);
+    if !o.success {
+        return Err(cmd_error(&st, &o));
+    }
+
+    let after = match validation {
+        Some(v) => Some(evaluate(&compressed, v, 1)?),
+        None => None,
+    };
+
+    let report = QuantizationReport {
+        original_size: fs::metadata(&original)?.len(),
+        compressed_size: fs::metadata(&compressed)?.len(),
+        before,
+        after,
+    };
+    Ok((compressed, report))
+}
+
+/// Sizes and (optionally) validation scores of a model before and after quantization.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizationReport {
+    /// size of the `.bin` model in bytes
+    pub original_size: u64,
+    /// size of the `.ftz` model in bytes
+    pub compressed_size: u64,
+    /// scores of the `.bin` model on the validation file, if one was given
+    pub before: Option<Evaluation>,
+    /// scores of the `.ftz` model on the validation file, if one was given
+    pub after: Option<Evaluation>,
+}
+
+impl QuantizationReport {
+    /// compressed size as a fraction of the original size.
+    pub fn compression_ratio(&self) -> f64 {
+        self.compressed_size as f64 / self.original_size as f64
+    }
+
+    /// P@1 lost by quantizing (negative if quantization helped). None without a validation file.
+    pub fn precision_loss(&self) -> Option<f64> {
+        match (&self.before, &self.after) {
+            (Some(b), Some(a)) => Some(b.precision - a.precision),
+            _ => None,
+        }
+    }
+
+    /// R@1 lost by quantizing (negative if quantization helped). None without a validation file.
+    pub fn recall_loss(&self) -> Option<f64> {
+        match (&self.before, &self.after) {
+            (Some(b), Some(a)) => Some(b.recall - a.recall),
+            _ => None,
+        }
+    }
+
+    /// Whether the compressed model lost at most `max_loss` of P@1 and R@1. Always true when no
+    /// validation file was used.
+    pub fn acceptable(&self, max_loss: f64) -> bool {
+        self.precision_loss().is_none_or(|l| l <= max_loss) &&
+            self.recall_loss().is_none_or(|l| l <= max_loss)
+    }
+}
+
+/// Precision and recall at k of a supervised model, as reported by fastText's test command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// number of examples in the test file
+    pub n: u64,
+    /// k used for P@k and R@k
+    pub k: u32,
+    pub precision: f64,
+    pub recall: f64,
+}
+
+/// Evaluates a supervised model on a labeled test file.
+///
+/// Documentation from fastText:
+///
+/// usage: fasttext test <model> <test-data> [<k>]
+///
+///  <model>      model filename
+///  <test-data>  test data filename (if -, read from stdin)
+///  <k>          (optional; 1 by default) predict top k labels
+pub fn evaluate(model: &str, test_data: &str, k: u32) -> io::Result<Evaluation> {
+    let st = s("test ") + model + " " + test_data + " " + &k.to_string();
+    let o = wrap_install(&st);
+    if !o.success {
+        return Err(cmd_error(&st, &o));
+    }
+    parse_evaluation(&o.stdout, k).ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidData, s("could not read test output for ") + model))
+}
+
+/// Error describing a failed fastText command.
+fn cmd_error(cmd: &str, o: &CommandOutput) -> io::Error {
+    io::Error::other(format!("fastText command failed with given input: {}\nstderr: {}", cmd, o.stderr))
+}
+
+/// Reads the "N", "P@k" and "R@k" lines printed by fastText's test command.
+fn parse_evaluation(stdout: &str, k: u32) -> Option<Evaluation> {
+    let (mut n, mut precision, mut recall) = (None, None, None);
+    for l in stdout.lines() {
+        let mut it = l.split('\t');
+        match (it.next(), it.next()) {
+            (Some("N"), Some(v)) => n = v.trim().parse::<u64>().ok(),
+            (Some(t), Some(v)) if t.starts_with("P@") => precision = v.trim().parse::<f64>().ok(),
+            (Some(t), Some(v)) if t.starts_with("R@") => recall = v.trim().parse::<f64>().ok(),
+            _ => (),
+        }
+    }
+    Some(Evaluation { n: n?, k, precision: precision?, recall: recall? })
 }
 
 
@@ -155,14 +311,14 @@ pub fn predict(model: &str, inp: &str, k: u32) -> Vec<Vec<String>> {
     let mut out = Vec::new();
     let s = s("predict ") + model + " " + inp + " " + &k.to_string();
     let r = wrap_install(&s);
-    for p in String::from_utf8_lossy(&r.stdout).split("\n") {
+    for p in r.stdout.split("\n") {
         let mut innerv = Vec::new();
         for v in p.split(" ") {
-            if v != "" {
+            if !v.is_empty() {
                 innerv.push(v.to_string());
             }
         }
-        if innerv.len() != 0 {
+        if !innerv.is_empty() {
             out.push(innerv);
         }
     }
@@ -179,48 +335,58 @@ pub fn predict(model: &str, inp: &str, k: u32) -> Vec<Vec<String>> {
 ///  <test-data>  test data filename (if -, read from stdin)
 ///  <k>          (optional; 1 by default) predict top k labels
 pub fn predict_prob(model: &str, inp: &str, k: u32) -> Vec<Vec<(String, f64)>> {
-    fn ext(l: &str) -> Vec<(String, f64)> {
-        let mut out = Vec::new();
-        let mut f = true;
-        let mut label = "";
-        for u in l.split(" ") {
-            if u != "" {
-                if f {
-                    label = u;
-                } else {
-                    out.push((label.to_string(), u.parse::<f64>().unwrap()));
-                }
-                f = !f;
-            }
-        }
-        if DEBUG { assert!(f); } // last value is a prob, not a label
-        out
-    }
     let mut out = Vec::new();
     let s = s("predict-prob ") + model + " " + inp + " " + &k.to_string();
     let r = wrap_install(&s);
-    for l in String::from_utf8_lossy(&r.stdout).split("\n") {
-        let v = ext(l);
-        if v.len() > 0 {
+    for l in r.stdout.split("\n") {
+        let v = label_probs(l);
+        if !v.is_empty() {
             out.push(v);
         }
     }
     out
 }
 
+/// reads a line of predict-prob output ("label prob label prob ...").
+fn label_probs(l: &str) -> Vec<(String, f64)> {
+    let mut out = Vec::new();
+    let mut f = true;
+    let mut label = "";
+    for u in l.split(" ") {
+        if !u.is_empty() {
+            if f {
+                label = u;
+            } else {
+                out.push((label.to_string(), u.parse::<f64>().unwrap()));
+            }
+            f = !f;
+        }
+    }
+    if DEBUG { assert!(f); } // last value is a prob, not a label
+    out
+}
 
 /// Helper function used to unspool arguments. S is a string with the primary fastText command
 /// (e.g. "skipgram") and args are the named arguments to be passed to it, with keys as the
 /// argument tag and values as the argument value.
-fn gen_mod<'a>(mut s: String, args: &HashMap<&str, &'a str>) {
-    for k in args.keys() {
-        s = s + " -" + k + " " + args.get(k).unwrap();
-    }
-    if !wrap_install(&s).status.success() {
+fn gen_mod(s: String, args: &HashMap<&str, &str>) {
+    let s = gen_cmd(s, args);
+    if !wrap_install(&s).success {
         panic!("Gen_mod failed with given input: {}", s)
     }
 }
 
+/// Builds the command string that gen_mod runs, with the arguments sorted so the same
+/// arguments always give the same command (which a recorded `MockBackend` relies on).
+fn gen_cmd(mut s: String, args: &HashMap<&str, &str>) -> String {
+    let mut keys: Vec<&&str> = args.keys().collect();
+    keys.sort();
+    for k in keys {
+        s = s + " -" + k + " " + args.get(k).unwrap();
+    }
+    s
+}
+
 /// Provides functionality for generating skipgrams.
 ///
 /// Include argument names as HashMap keys and argument values as HashMap values, e.g.:
@@ -254,7 +420,7 @@ fn gen_mod<'a>(mut s: String, args: &HashMap<&str, &'a str>) {
 ///  -ws                 size of the context window [5]
 ///  -epoch              number of epochs [5]
 ///  -neg                number of negatives sampled [5]
-///  -loss               loss function {ns, hs, softmax} [ns]
+///  -loss               loss function {ns, hs, softmax, one-vs-all} [ns]
 ///  -thread             number of threads [12]
 ///  -pretrainedVectors  pretrained word vectors for supervised learning []
 ///  -saveOutput         whether output params should be saved [0]
@@ -301,7 +467,7 @@ pub fn skipgram(args: &HashMap<&str, &str>) {
 ///  -ws                 size of the context window [5]
 ///  -epoch              number of epochs [5]
 ///  -neg                number of negatives sampled [5]
-///  -loss               loss function {ns, hs, softmax} [ns]
+///  -loss               loss function {ns, hs, softmax, one-vs-all} [ns]
 ///  -thread             number of threads [12]
 ///  -pretrainedVectors  pretrained word vectors for supervised learning []
 ///  -saveOutput         whether output params should be saved [0]
@@ -344,7 +510,7 @@ pub fn cbow(args: &HashMap<&str, &str>) {
 ///  -ws                 size of the context window [5]
 ///  -epoch              number of epochs [5]
 ///  -neg                number of negatives sampled [5]
-///  -loss               loss function {ns, hs, softmax} [ns]
+///  -loss               loss function {ns, hs, softmax, one-vs-all} [ns]
 ///  -thread             number of threads [12]
 ///  -pretrainedVectors  pretrained word vectors for supervised learning []
 ///  -saveOutput         whether output params should be saved [0]
@@ -358,7 +524,7 @@ pub fn cbow(args: &HashMap<&str, &str>) {
 pub fn min_skipgram(input: &str, output: &str) -> String {
     let st = s("skipgram -input ") + input + " -output " + output;
     let o = wrap_install(&st);
-    if o.status.success() {
+    if o.success {
         s(output) + ".bin"
     } else {
         panic!("Min_skipgram failed with given input: {} \noutput: {:?}", st, o)
@@ -394,7 +560,7 @@ pub fn min_skipgram(input: &str, output: &str) -> String {
 ///  -ws                 size of the context window [5]
 ///  -epoch              number of epochs [5]
 ///  -neg                number of negatives sampled [5]
-///  -loss               loss function {ns, hs, softmax} [ns]
+///  -loss               loss function {ns, hs, softmax, one-vs-all} [ns]
 ///  -thread             number of threads [12]
 ///  -pretrainedVectors  pretrained word vectors for supervised learning []
 ///  -saveOutput         whether output params should be saved [0]
@@ -407,7 +573,7 @@ pub fn min_skipgram(input: &str, output: &str) -> String {
 ///  -dsub               size of each sub-vector [2]
 pub fn min_cbow(input: &str, output: &str) -> String {
     let st = s("cbow -input ") + input + " -output " + output;
-    if wrap_install(&st).status.success() {
+    if wrap_install(&st).success {
         s(output) + ".bin"
     } else {
         panic!("Cbow failed with given input: {}", st)
@@ -415,7 +581,7 @@ pub fn min_cbow(input: &str, output: &str) -> String {
 }
 
 
-fn resp(sm: &str, stdout: std::borrow::Cow<str>) -> Vec<Vec<(String, f64)>> {
+fn resp(sm: &str, stdout: &str) -> Vec<Vec<(String, f64)>> {
     let mut v0 = Vec::new();
     if DEBUG {
         println!("Beginning match iteration");
@@ -439,33 +605,13 @@ fn resp(sm: &str, stdout: std::borrow::Cow<str>) -> Vec<Vec<(String, f64)>> {
                 panic!("misformatted line in input: {}", l);
             }
         }
-        if v1.len() > 0 {
+        if !v1.is_empty() {
             v0.push(v1);
         }
     }
     v0
 }
 
-/// runs an arbitrary command and makes sure that the fasttext is set up locally.
-fn run_cmd(cmd: &str) -> Output {
-    if DEBUG { println!("cmd: {}", cmd); }
-    let r = Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
-        .stdout(Stdio::piped())
-        .output()
-        .expect("failed to execute process");
-    if !r.status.success() && !Path::new("./fasttext").exists() {
-        let ir = install();
-        for o in ir.iter() {
-            if !o.status.success() { panic!("Missing files / executable"); }
-        }
-    }
-    if DEBUG { println!("{:?}", r); }
-    r
-}
-
-
 /// Nearest neighbors. Input of "words" are single words separated by spaces.
 ///
 /// Full documentation from FastText:
@@ -476,8 +622,8 @@ fn run_cmd(cmd: &str) -> Output {
 ///  <k>          (optional; 10 by default) predict top k labels
 pub fn nn(words: &str, model: &str, k: u32) -> Vec<Vec<(String, f64)>> {
     if DEBUG { println!("NN begun") };
-    let cmd = s("echo ") + words + " | ./fasttext nn " + model + " " + &k.to_string();
-    resp("Query word? ", String::from_utf8_lossy(&run_cmd(&cmd).stdout))
+    let cmd = s("nn ") + model + " " + &k.to_string();
+    resp("Query word? ", &backend::run(&cmd, Some(&(s(words) + "\n"))).stdout)
 }
 
 /// Access to the analogies function. Not supported.
@@ -488,36 +634,36 @@ pub fn nn(words: &str, model: &str, k: u32) -> Vec<Vec<(String, f64)>> {
 ///
 ///  <model>      model filename
 ///  <k>          (optional; 10 by default) predict top k labels
+#[allow(unreachable_code, unused_variables)]
 pub fn analogies(analogies: &str, model: &str, k: u32) -> Vec<Vec<(String, f64)>> {
     unimplemented!();
-    let cmd = s("echo \"") + analogies + "\" | ./fasttext analogies " + model + " " + &k.to_string();
-    // just doing the "echo "cmd" | ./fasttext [...]" thing won't work here since it just keeps
-    // checking stdin and re-outputting results.
-    resp("Query triplet (A - B + C)? ", String::from_utf8_lossy(&run_cmd(&cmd).stdout))
+    let cmd = s("analogies ") + model + " " + &k.to_string();
+    // just passing the triplets on stdin won't work here since fastText just keeps checking
+    // stdin and re-outputting results.
+    resp("Query triplet (A - B + C)? ", &backend::run(&cmd, Some(&(s(analogies) + "\n"))).stdout)
 }
 
-fn parse_vec(cmd: &str, sentence: Option<&str>) -> Vec<Vec<f64>> {
+fn parse_vec(cmd: &str, input: &str, words: bool) -> Vec<Vec<f64>> {
+    parse_vec_output(backend::run(cmd, Some(&(s(input) + "\n"))).stdout, words)
+}
+
+/// reads the vectors printed by print-word-vectors, whose lines start with the word, or by
+/// print-sentence-vectors.
+fn parse_vec_output(st: String, words: bool) -> Vec<Vec<f64>> {
     let mut out = Vec::new();
-    let mut st = String::from_utf8_lossy(&run_cmd(cmd).stdout).to_string();
-    match sentence {
-        None => (),
-        Some(sent) => {
-            st = st.replace(sent, "");
-        }
-    };
     for l in st.split("\n") {
         let mut wordvec = Vec::new();
-        let mut f = true;
+        let mut f = words;
         for t in l.split(" ") {
             if f {
                 f = false;
             } else {
-                if t != "" {
+                if !t.is_empty() {
                     wordvec.push(t.parse::<f64>().unwrap());
                 }
             }
         }
-        if wordvec.len() > 0 {
+        if !wordvec.is_empty() {
             out.push(wordvec);
         }
     }
@@ -529,8 +675,8 @@ fn parse_vec(cmd: &str, sentence: Option<&str>) -> Vec<Vec<f64>> {
 /// Input: one or more words (separated by spaces)
 /// Output: A vec of word vectors (one for each input word)
 pub fn word_vector(words: &str, model: &str) -> Vec<Vec<f64>> {
-    let cmd = s("echo \"") + words + "\" | ./fasttext print-word-vectors " + model;
-    parse_vec(&cmd, None)
+    let cmd = s("print-word-vectors ") + model;
+    parse_vec(&cmd, words, true)
 }
 
 
@@ -539,27 +685,53 @@ pub fn word_vector(words: &str, model: &str) -> Vec<Vec<f64>> {
 /// Input: sentence
 /// Output: A vec of a sentence vector
 pub fn sentence_vector(sentence: &str, model: &str) -> Vec<Vec<f64>> {
-    let cmd = s("echo \"") + sentence + "\" | ./fasttext print-sentence-vectors " + model;
-    parse_vec(&cmd, Some(sentence))
+    let cmd = s("print-sentence-vectors ") + model;
+    parse_vec(&cmd, sentence, false)
 }
 
 
-/// the objective for testing here is not to check that the fasttext binary is working as expected,
-/// but that it can be install and that its output can be consistently read.
+// the objective for testing here is not to check that the fasttext binary is working as expected,
+// but that its output can be consistently read. The commands are replayed from fixtures, so
+// only the tests that need the binary's files (ignored by default) run it.
 
 #[cfg(test)]
 mod tests {
     extern crate kolmogorov_smirnov as ks;
 
-    use std::{thread, time};
     use std::collections::HashSet;
+    use std::env;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::thread;
+    use backend::{set_backend, Backend, CommandOutput, MockBackend, Subprocess};
     use super::*;
 
-    fn check_exists(file: &str, or: fn()) {
-        if !Path::new(file).exists() {
-            thread::sleep(time::Duration::from_secs(30));
-            if !Path::new(file).exists() {
-                or()
+    /// Replays the fastText commands of a test from `fixtures/<name>.txt`, so that it needs
+    /// neither the binary nor the network. With `FAST_TEXT_RECORD` set, the commands are run
+    /// by the binary instead, and recorded there when the test passes.
+    struct Fixture {
+        path: PathBuf,
+        mock: Rc<MockBackend>,
+        previous: Rc<dyn Backend>,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let path = Path::new("fixtures").join(s(name) + ".txt");
+            let mock = Rc::new(match env::var_os("FAST_TEXT_RECORD") {
+                Some(_) => MockBackend::record(Box::new(Subprocess)),
+                None => MockBackend::load(&path).unwrap(),
+            });
+            let previous = set_backend(mock.clone());
+            Fixture { path, mock, previous }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            set_backend(self.previous.clone());
+            if env::var_os("FAST_TEXT_RECORD").is_some() && !thread::panicking() {
+                self.mock.save(&self.path).unwrap();
             }
         }
     }
@@ -591,15 +763,8 @@ mod tests {
         c![v, for v in a.intersection(b)].len()
     }
 
-    fn inst() {
-        check_exists("fasttext", || { install(); });
-    }
-
-    fn samp() {
-        check_exists("sample.bin", sample_skipgram);
-    }
-
     #[test]
+    #[ignore = "downloads and builds fastText"]
     fn test_install() {
         let rv = install();
         for r in rv.iter() {
@@ -619,43 +784,36 @@ mod tests {
         assert_eq!(r.status.code(), Some(1)); // returns 127 if ./fasttext DNE
     }
 
-    /// generate a skipgram model for testing things like the nearest neighbor function.
-    fn sample_skipgram() {
-        inst();
-        let model = min_skipgram("sample_text.txt", "sample");
-        println!("Generated skipgram model: {}", model);
-    }
-
     #[test]
     fn test_nn() {
-        samp();
+        let _f = Fixture::new("nn");
+        let model = min_skipgram("sample_text.txt", "test_nn");
 
-        let out = nn("lesbian", "sample.bin", 10);
+        let out = nn("lesbian", &model, 10);
         println!("{:?}", out);
         assert_eq!(out.len(), 1); // number of words queried
         assert_eq!(out[0].len(), 10); // k
 
-        let out = nn("lesbian gay", "sample.bin", 5);
+        let out = nn("lesbian gay", &model, 5);
         println!("{:?}", out);
         assert_eq!(out.len(), 2);
         assert_eq!(out[0].len(), 5);
 
-        let out = nn("lesbian gay bisexual", "sample.bin", 8);
+        let out = nn("lesbian gay bisexual", &model, 8);
         println!("{:?}", out);
         assert_eq!(out.len(), 3);
         assert_eq!(out[0].len(), 8);
 
-        let out = nn("lesbian gay bisexual transgender", "sample.bin", 1);
+        let out = nn("lesbian gay bisexual transgender", &model, 1);
         println!("{:?}", out);
         assert_eq!(out.len(), 4);
         assert_eq!(out[0].len(), 1);
+        rm(vec!["test_nn.*"]);
     }
 
 
     /// test nearest neighbors for two functions yields valid results.
     fn test_embedding(min_fn: fn(&str, &str) -> String, reg_fn: fn(&HashMap<&str, &str>), min_name: &str, reg_name: &str) {
-        inst();
-
         let input = "sample_text.txt";
         let mut args = HashMap::new();
         args.insert("input", input);
@@ -681,21 +839,37 @@ mod tests {
             assert!(sim(&set(r1), &set(r2)) > (0.9 * k as f64) as usize);
         }
 
-        let r1 = s(min_name) + "*";
-        let r2 = s(reg_name) + "*";
+        let r1 = s(min_name) + ".*";
+        let r2 = s(reg_name) + ".*";
         rm(vec![&r1, &r2]);
     }
 
     #[test]
     fn test_skipgram() {
+        let _f = Fixture::new("skipgram");
         test_embedding(min_skipgram, skipgram, "test_min_skipgram", "test_skipgram");
     }
 
     #[test]
     fn test_cbow() {
+        let _f = Fixture::new("cbow");
         test_embedding(min_cbow, cbow, "test_min_cbow", "test_cbow");
     }
 
+    /// the binary reads natively trained models, and finds the same neighbours in them.
+    fn test_native_embedding(model: ModelName, name: &str) {
+        let native = s(name) + "_native.bin";
+        let m = Trainer::new(TrainArgs { model, ..TrainArgs::default() }).train("sample_text.txt").unwrap();
+        m.save(&native).unwrap();
+        let k = 10;
+        for w in ["friend", "day", "door"].iter() {
+            let r1 = nn(w, &native, k);
+            let r2 = vec![m.nearest_neighbors(w, k as usize).into_iter().map(|(n, d)| (n, d as f64)).collect()];
+            assert!(sim(&set(r1), &set(r2)) > (0.9 * k as f64) as usize);
+        }
+        rm(vec![&native]);
+    }
+
     fn test_predict(model: String) {
         let p = predict(&model, "t.txt", 1);
         println!("test_predict output: {:?}", p);
@@ -719,12 +893,116 @@ mod tests {
         assert_eq!(p[0].len(), 2);
         assert_eq!(p.len(), 2);
     }
+    fn test_quantize(model: &str) {
+        let args: HashMap<_, _> = vec![
+            ("input", "sample_text.txt"),
+            ("output", model),
+        ].into_iter().collect();
+
+        let (ftz, report) = quantize(&args, Some("sample_text.txt")).unwrap();
+        println!("quantization report: {:?}", report);
+        assert_eq!(ftz, s(model) + ".ftz");
+        assert!(Path::new(&ftz).exists());
+        assert!(report.compressed_size < report.original_size);
+        assert_eq!(report.before.as_ref().unwrap().n, report.after.as_ref().unwrap().n);
+        assert!(report.precision_loss().is_some());
+        assert!(report.acceptable(1.0));
+    }
+
+    /// the native reader should agree with the fastText binary on a quantized model.
+    fn test_native_ftz(model: &str) {
+        let ftz = s(model) + ".ftz";
+        let m = FastTextModel::load(&ftz).unwrap();
+        assert!(m.is_quantized());
+
+        let text = fs::read_to_string("t.txt").unwrap();
+        let cli = predict(&ftz, "t.txt", 1);
+        assert_eq!(cli.len(), text.lines().count());
+        for (l, p) in text.lines().zip(cli.iter()) {
+            assert_eq!(m.predict(l, 1)[0].0, p[0]);
+        }
+
+        let words = ["military", "day", "frabjous"];
+        let cli = word_vector(&words.join(" "), &ftz);
+        assert_eq!(cli.len(), words.len());
+        for (w, v) in words.iter().zip(cli.iter()) {
+            let native = m.word_vector(w);
+            assert_eq!(native.len(), v.len());
+            for (a, b) in native.iter().zip(v.iter()) {
+                assert!((*a as f64 - b).abs() < 1e-3);
+            }
+        }
+    }
+
+    /// a model saved natively should be byte-for-byte the model fastText wrote.
+    fn test_save(model: &str, saved: &str) {
+        FastTextModel::load(model).unwrap().save(saved).unwrap();
+        assert_eq!(fs::read(model).unwrap(), fs::read(saved).unwrap());
+    }
+
+    /// a model quantized natively should be readable by the fastText binary.
+    fn test_native_quantize(model: &str) {
+        let m = FastTextModel::load(s(model) + ".bin").unwrap();
+        let ftz = s(model) + "_native.ftz";
+        let args = QuantizeArgs { cutoff: 1000, ..QuantizeArgs::default() };
+        let q = m.quantize(&args, &ftz, None).unwrap();
+
+        let text = fs::read_to_string("t.txt").unwrap();
+        let cli = predict(&ftz, "t.txt", 1);
+        assert_eq!(cli.len(), text.lines().count());
+        for (l, p) in text.lines().zip(cli.iter()) {
+            assert_eq!(q.predict(l, 1)[0].0, p[0]);
+        }
+    }
+
+    /// a natively trained model, with the fingerprint of reproducible training after its
+    /// matrices, is readable by the fastText binary, which scores it the same.
+    fn test_native_train(model: &str) {
+        let native = Trainer::new(TrainArgs::supervised()).reproducible(1).train("sample_text.txt").unwrap();
+        let bin = s(model) + "_trained.bin";
+        native.save(&bin).unwrap();
+        let cli = evaluate(&bin, "sample_text.txt", 1).unwrap();
+        let e = native.test("sample_text.txt", 1).unwrap();
+        assert_eq!(e.n, cli.n);
+        assert!((e.precision - cli.precision).abs() < 1e-3, "{:?} {:?}", e, cli);
+    }
 
     #[test]
-    fn test_supervised_and_predicts() {
-        inst();
+    fn test_parse_evaluation() {
+        let e = parse_evaluation("N\t200\nP@1\t0.955\nR@1\t0.955\n", 1).unwrap();
+        assert_eq!(e, Evaluation { n: 200, k: 1, precision: 0.955, recall: 0.955 });
+        assert!(parse_evaluation("Number of examples: 200", 1).is_none());
+    }
+
+    #[test]
+    fn test_gen_cmd() {
+        let args: HashMap<_, _> = vec![("output", "m"), ("input", "t.txt"), ("dim", "10")].into_iter().collect();
+        assert_eq!(gen_cmd(s("supervised"), &args), "supervised -dim 10 -input t.txt -output m");
+    }
 
-        let model = "sup";
+    #[test]
+    fn test_mocked_commands() {
+        let mock = Rc::new(MockBackend::new()
+            .on("predict-prob m.bin t.txt 2", "__label__a 0.75 __label__b 0.25\n")
+            .on_output("nn m.bin 2", Some("cheese\n"),
+                       CommandOutput::ok("Query word? sauce 0.9\npie 0.8\nQuery word? "))
+            .on_output("print-word-vectors m.bin", Some("a b\n"), CommandOutput::ok("a 0.5 1\nb -1 2\n"))
+            .on_output("print-sentence-vectors m.bin", Some("a b\n"), CommandOutput::ok("0.25 1.5 \n")));
+        let previous = set_backend(mock.clone());
+        assert_eq!(predict_prob("m.bin", "t.txt", 2),
+                   vec![vec![(s("__label__a"), 0.75), (s("__label__b"), 0.25)]]);
+        assert_eq!(nn("cheese", "m.bin", 2), vec![vec![(s("sauce"), 0.9), (s("pie"), 0.8)]]);
+        assert_eq!(word_vector("a b", "m.bin"), vec![vec![0.5, 1.0], vec![-1.0, 2.0]]);
+        assert_eq!(sentence_vector("a b", "m.bin"), vec![vec![0.25, 1.5]]);
+        assert!(evaluate("m.bin", "t.txt", 1).is_err());
+        assert_eq!(mock.calls().len(), 5);
+        set_backend(previous);
+    }
+
+    #[test]
+    fn test_supervised_and_predicts() {
+        let _f = Fixture::new("supervised");
+        let model = "test_sup";
 
         let args: HashMap<_, _> = vec![
             ("input", "sample_text.txt"),
@@ -736,14 +1014,90 @@ mod tests {
         test_predict(s(model) + ".bin");
         test_predict_prob(s(model) + ".bin");
 
+        let m = s(model) + ".*";
+        rm(vec![&m]);
+    }
+
+    /// the probabilities of a hierarchical softmax model agree with the binary's.
+    #[test]
+    fn test_hs_predict_prob() {
+        let _f = Fixture::new("hs_predict_prob");
+        let args = TrainArgs { loss: LossName::HierarchicalSoftmax, epoch: 25, lr: 1.0, ..TrainArgs::supervised() };
+        let m = Trainer::new(args).reproducible(1).train("sample_text.txt").unwrap();
+        let model = "test_hs_predict_prob.bin";
+        m.save(model).unwrap();
+
+        let text = fs::read_to_string("t.txt").unwrap();
+        let cli = predict_prob(model, "t.txt", 3);
+        assert_eq!(cli.len(), text.lines().count());
+        for (l, p) in text.lines().zip(cli.iter()) {
+            let native = m.predict(l, 3);
+            assert_eq!(native.len(), p.len());
+            for ((a, x), (b, y)) in native.iter().zip(p.iter()) {
+                assert_eq!(a, b);
+                assert!((*x as f64 - y).abs() < 1e-4, "{} {}", x, y);
+            }
+        }
+        rm(vec![model]);
+    }
+
+    /// checks the binary and this crate against each other on the files they write.
+    #[test]
+    #[ignore = "runs the fastText binary"]
+    fn test_binary_supervised() {
+        let model = "test_binary_sup";
+
+        let args: HashMap<_, _> = vec![
+            ("input", "sample_text.txt"),
+            ("output", model),
+        ].into_iter().collect();
+
+        supervised(&args);
+
+        test_quantize(model);
+        test_native_ftz(model);
+        test_save(&(s(model) + ".bin"), &(s(model) + "_saved.bin"));
+        assert_eq!(predict(&(s(model) + ".bin"), "t.txt", 2), predict(&(s(model) + "_saved.bin"), "t.txt", 2));
+        test_native_quantize(model);
+        test_native_train(model);
+
         let m = s(model) + "*";
         rm(vec![&m]);
     }
 
+    /// checks the binary and this crate against each other on the files they write.
+    #[test]
+    #[ignore = "runs the fastText binary"]
+    fn test_binary_unsupervised() {
+        let model = min_skipgram("sample_text.txt", "test_binary_unsup");
+        let m = FastTextModel::load(&model).unwrap();
+        let r = vec_file::VecReader::open("test_binary_unsup.vec").unwrap();
+        assert_eq!(r.dim(), m.dim());
+        let n = r.len();
+        let entries: Vec<_> = r.collect::<io::Result<_>>().unwrap();
+        assert_eq!(entries.len(), n);
+        for (w, v) in entries.iter().take(20) {
+            for (a, b) in v.iter().zip(m.word_vector(w)) {
+                assert!((a - b).abs() < 1e-3);
+            }
+        }
+
+        let saved = "test_binary_unsup_saved.bin";
+        test_save(&model, saved);
+        let words = "lesbian gay queen";
+        assert_eq!(word_vector(words, &model), word_vector(words, saved));
+        assert_eq!(nn(words, &model, 5), nn(words, saved, 5));
+
+        test_native_embedding(ModelName::Skipgram, "test_binary_unsup");
+        test_native_embedding(ModelName::Cbow, "test_binary_unsup");
+        rm(vec!["test_binary_unsup*"]);
+    }
+
     #[test]
     fn test_word_vector() {
-        samp();
-        let v = word_vector("gay math queen", "sample.bin");
+        let _f = Fixture::new("word_vector");
+        let model = min_skipgram("sample_text.txt", "test_word_vector");
+        let v = word_vector("gay math queen", &model);
         assert_eq!(v.len(), 3); // three words go in, three wordvecs come out
 
         let mut hs = HashSet::new();
@@ -752,14 +1106,18 @@ mod tests {
         }
         assert_eq!(hs.len(), 1); // vectors are all the same length
 
-        let v = word_vector("naps", "sample.bin");
+        let v = word_vector("naps", &model);
         assert_eq!(v.len(), 1);
+        rm(vec!["test_word_vector.*"]);
     }
 
     #[test]
     fn test_sentence_vector() {
-        samp();
-        let v = sentence_vector("To die, to sleep – to sleep, perchance to dream – ay, there's the rub, for in this sleep of death what dreams may come…", "sample.bin");
+        let _f = Fixture::new("sentence_vector");
+        let model = min_skipgram("sample_text.txt", "test_sentence_vector");
+        let v = sentence_vector("To die, to sleep – to sleep, perchance to dream – ay, there's the rub, for in this sleep of death what dreams may come…", &model);
         assert_eq!(v.len(), 1);
+        assert_eq!(v[0].len(), 100);
+        rm(vec!["test_sentence_vector.*"]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,167 @@
+//! Dense row-major matrices of f32, as stored in fastText model files.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use bin_io::*;
+
+/// The values of a matrix: in memory, or a view of a memory-mapped model file. Mapped values
+/// are little-endian and not necessarily aligned, so they are decoded as they are read.
+#[derive(Clone)]
+enum Data {
+    Owned(Vec<f32>),
+    Mapped(Arc<Mmap>, usize),
+}
+
+#[derive(Clone)]
+pub struct Matrix {
+    m: usize,
+    n: usize,
+    data: Data,
+}
+
+fn decode(b: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    b.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+}
+
+impl Matrix {
+    /// a m x n matrix over row-major data.
+    pub fn new(m: usize, n: usize, data: Vec<f32>) -> Matrix {
+        assert_eq!(data.len(), m * n, "matrix data does not have m x n values");
+        Matrix { m, n, data: Data::Owned(data) }
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Matrix> {
+        let m = read_size(r)?;
+        let n = read_size(r)?;
+        let len = m.checked_mul(n).ok_or_else(|| invalid("matrix too large"))?;
+        let data = read_f32s(r, len)?;
+        Ok(Matrix::new(m, n, data))
+    }
+
+    /// Reads a matrix from `r`, the part of `map` that has not been parsed yet, without
+    /// copying its values: the matrix keeps a view of the mapping and `r` is advanced past it.
+    pub fn read_mapped(map: &Arc<Mmap>, r: &mut &[u8]) -> io::Result<Matrix> {
+        let m = read_size(r)?;
+        let n = read_size(r)?;
+        let len = m.checked_mul(n).and_then(|s| s.checked_mul(4))
+            .ok_or_else(|| invalid("matrix too large"))?;
+        if r.len() < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "model file ends inside a matrix"));
+        }
+        let offset = map.len() - r.len();
+        *r = &r[len..];
+        Ok(Matrix { m, n, data: Data::Mapped(map.clone(), offset) })
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_i64(w, self.m as i64)?;
+        write_i64(w, self.n as i64)?;
+        match self.data {
+            Data::Owned(ref v) => write_f32s(w, v),
+            Data::Mapped(ref map, offset) => w.write_all(&map[offset..offset + self.m * self.n * 4]),
+        }
+    }
+
+    /// Whether the values are a view of a memory-mapped file.
+    pub fn is_mapped(&self) -> bool {
+        match self.data {
+            Data::Mapped(..) => true,
+            Data::Owned(_) => false,
+        }
+    }
+
+    /// a new matrix made of the given rows of this one, in that order.
+    pub fn select_rows(&self, idx: &[i32]) -> Matrix {
+        let mut data = Vec::with_capacity(idx.len() * self.n);
+        for &i in idx {
+            data.extend_from_slice(&self.row(i as usize));
+        }
+        Matrix::new(idx.len(), self.n, data)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.m
+    }
+
+    pub fn cols(&self) -> usize {
+        self.n
+    }
+
+    /// bytes of row i in the mapping.
+    fn mapped_row<'a>(&self, map: &'a Mmap, offset: usize, i: usize) -> &'a [u8] {
+        let start = offset + i * self.n * 4;
+        &map[start..start + self.n * 4]
+    }
+
+    /// row i, borrowed unless it has to be decoded from a mapping.
+    pub fn row(&self, i: usize) -> Cow<'_, [f32]> {
+        match self.data {
+            Data::Owned(ref v) => Cow::Borrowed(&v[i * self.n..(i + 1) * self.n]),
+            Data::Mapped(ref map, offset) => Cow::Owned(decode(self.mapped_row(map, offset, i)).collect()),
+        }
+    }
+
+    /// row i for writing; a mapped matrix is first copied into memory.
+    pub fn row_mut(&mut self, i: usize) -> &mut [f32] {
+        if let Data::Mapped(..) = self.data {
+            self.data = Data::Owned(self.data().into_owned());
+        }
+        match self.data {
+            Data::Owned(ref mut v) => &mut v[i * self.n..(i + 1) * self.n],
+            Data::Mapped(..) => unreachable!(),
+        }
+    }
+
+    /// all values, row after row.
+    pub fn data(&self) -> Cow<'_, [f32]> {
+        match self.data {
+            Data::Owned(ref v) => Cow::Borrowed(v),
+            Data::Mapped(ref map, offset) => Cow::Owned(decode(&map[offset..offset + self.m * self.n * 4]).collect()),
+        }
+    }
+
+    /// l2 norm of every row.
+    pub fn row_norms(&self) -> Vec<f32> {
+        (0..self.m).map(|i| self.row(i).iter().map(|x| x * x).sum::<f32>().sqrt()).collect()
+    }
+
+    /// adds `a` times row i to x.
+    pub fn add_row_to(&self, x: &mut [f32], i: usize, a: f32) {
+        match self.data {
+            Data::Owned(ref v) => for (xj, rj) in x.iter_mut().zip(&v[i * self.n..(i + 1) * self.n]) {
+                *xj += a * rj;
+            },
+            Data::Mapped(ref map, offset) => for (xj, rj) in x.iter_mut().zip(decode(self.mapped_row(map, offset, i))) {
+                *xj += a * rj;
+            },
+        }
+    }
+
+    pub fn dot_row(&self, x: &[f32], i: usize) -> f32 {
+        match self.data {
+            Data::Owned(ref v) => x.iter().zip(&v[i * self.n..(i + 1) * self.n]).map(|(a, b)| a * b).sum(),
+            Data::Mapped(ref map, offset) => x.iter().zip(decode(self.mapped_row(map, offset, i))).map(|(a, b)| a * b).sum(),
+        }
+    }
+}
+
+impl PartialEq for Matrix {
+    fn eq(&self, other: &Matrix) -> bool {
+        self.m == other.m && self.n == other.n && self.data() == other.data()
+    }
+}
+
+impl fmt::Debug for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Matrix")
+            .field("m", &self.m)
+            .field("n", &self.n)
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
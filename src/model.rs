@@ -0,0 +1,1250 @@
+//! Native reader for fastText's `.bin` models and quantized `.ftz` models, so vectors and
+//! predictions can be computed without running the fastText binary.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use args::{LossName, ModelName, QuantizeArgs, TrainArgs};
+use bin_io::*;
+use dictionary::{Dictionary, Entry, EntryType, EOS};
+use huffman::HuffmanTree;
+use matrix::Matrix;
+use pca::Pca;
+use quantizer::QMatrix;
+use train;
+use vec_file::VecWriter;
+use Evaluation;
+
+pub const FASTTEXT_FILEFORMAT_MAGIC_INT32: i32 = 793712314;
+pub const FASTTEXT_VERSION: i32 = 12;
+/// marks the training fingerprint written after the matrices; fastText ignores it.
+const FINGERPRINT_MAGIC: i32 = 0x46545250;
+
+/// A matrix as stored in a model file: dense for `.bin`, product-quantized for `.ftz`.
+#[derive(Debug, Clone)]
+enum Weights {
+    Dense(Matrix),
+    Quantized(QMatrix),
+}
+
+impl Weights {
+    fn rows(&self) -> usize {
+        match *self {
+            Weights::Dense(ref m) => m.rows(),
+            Weights::Quantized(ref q) => q.rows(),
+        }
+    }
+
+    fn cols(&self) -> usize {
+        match *self {
+            Weights::Dense(ref m) => m.cols(),
+            Weights::Quantized(ref q) => q.cols(),
+        }
+    }
+
+    fn add_row_to(&self, x: &mut [f32], i: usize, a: f32) {
+        match *self {
+            Weights::Dense(ref m) => m.add_row_to(x, i, a),
+            Weights::Quantized(ref q) => q.add_row_to(x, i, a),
+        }
+    }
+
+    fn dot_row(&self, x: &[f32], i: usize) -> f32 {
+        match *self {
+            Weights::Dense(ref m) => m.dot_row(x, i),
+            Weights::Quantized(ref q) => q.dot_row(x, i),
+        }
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match *self {
+            Weights::Dense(ref m) => m.write(w),
+            Weights::Quantized(ref q) => q.write(w),
+        }
+    }
+}
+
+/// A fastText model loaded into memory.
+#[derive(Debug, Clone)]
+pub struct FastTextModel {
+    args: TrainArgs,
+    dict: Dictionary,
+    input: Weights,
+    output: Weights,
+    /// only for models trained with hierarchical softmax
+    tree: Option<HuffmanTree>,
+    /// hash of the training data and arguments, for models trained reproducibly
+    fingerprint: Option<u64>,
+}
+
+/// What `nearest_labels` compares labels to: a text, embedded like `sentence_vector`, or a
+/// vector of the model's dimension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LabelQuery<'a> {
+    Text(&'a str),
+    Vector(&'a [f32]),
+}
+
+impl<'a> From<&'a str> for LabelQuery<'a> {
+    fn from(text: &'a str) -> LabelQuery<'a> {
+        LabelQuery::Text(text)
+    }
+}
+
+impl<'a> From<&'a [f32]> for LabelQuery<'a> {
+    fn from(v: &'a [f32]) -> LabelQuery<'a> {
+        LabelQuery::Vector(v)
+    }
+}
+
+impl<'a> From<&'a Vec<f32>> for LabelQuery<'a> {
+    fn from(v: &'a Vec<f32>) -> LabelQuery<'a> {
+        LabelQuery::Vector(v)
+    }
+}
+
+/// log with the same smoothing fastText applies to probabilities, computed in double
+/// precision like fastText's.
+pub fn std_log(x: f32) -> f32 {
+    (f64::from(x) + 1e-5).ln() as f32
+}
+
+/// the logistic function, 0 or 1 beyond ±8 as in training.
+pub fn sigmoid(x: f32) -> f32 {
+    if x < -8.0 {
+        0.0
+    } else if x > 8.0 {
+        1.0
+    } else {
+        1.0 / (1.0 + (-x).exp())
+    }
+}
+
+/// `sigmoid` read from fastText's 512-entry table over [-8, 8], which one-vs-all prediction
+/// uses: the value at the table entry below x rather than at x.
+fn table_sigmoid(x: f32) -> f32 {
+    const SIZE: i64 = 512;
+    const MAX: f32 = 8.0;
+    if x < -MAX {
+        0.0
+    } else if x > MAX {
+        1.0
+    } else {
+        let i = ((x + MAX) * SIZE as f32 / MAX / 2.0) as i64;
+        let t = (i * 2 * MAX as i64) as f32 / SIZE as f32 - MAX;
+        (1.0 / f64::from(1.0 + (-t).exp())) as f32
+    }
+}
+
+fn norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let (na, nb) = (norm(a), norm(b));
+    if na == 0.0 || nb == 0.0 {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>() / (na * nb)
+}
+
+fn scale(v: &mut [f32], a: f32) {
+    for x in v.iter_mut() {
+        *x *= a;
+    }
+}
+
+impl FastTextModel {
+    /// Loads a `.bin` or `.ftz` model file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<FastTextModel> {
+        FastTextModel::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Loads a `.bin` or `.ftz` model by memory-mapping the file. The dense matrices are views
+    /// into the mapping rather than copies, so processes loading the same file share its pages
+    /// and only the dictionary has to be parsed. The file must not be modified while the model
+    /// is in use.
+    pub fn load_mmap<P: AsRef<Path>>(path: P) -> io::Result<FastTextModel> {
+        let file = File::open(path)?;
+        // safe as long as the file is not truncated or rewritten while mapped, as documented
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        let mut r = &map[..];
+        FastTextModel::read_with(&mut r, |r| Matrix::read_mapped(&map, r))
+    }
+
+    /// Reads a model in the format written by fastText's saveModel.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<FastTextModel> {
+        FastTextModel::read_with(r, Matrix::read)
+    }
+
+    /// reads a model, with the dense matrices read by read_dense.
+    fn read_with<R: Read, F>(r: &mut R, read_dense: F) -> io::Result<FastTextModel>
+        where F: Fn(&mut R) -> io::Result<Matrix>
+    {
+        if read_i32(r)? != FASTTEXT_FILEFORMAT_MAGIC_INT32 {
+            return Err(invalid("not a fastText model file"));
+        }
+        let version = read_i32(r)?;
+        if version != 11 && version != FASTTEXT_VERSION {
+            return Err(invalid("unsupported fastText model file version"));
+        }
+        let mut args = TrainArgs::read(r)?;
+        if version == 11 && args.model == ModelName::Supervised {
+            // old supervised models stored maxn without using it
+            args.maxn = 0;
+        }
+        let dict = Dictionary::read(r, &args)?;
+
+        let quant_input = read_bool(r)?;
+        let input = if quant_input {
+            Weights::Quantized(QMatrix::read(r)?)
+        } else {
+            Weights::Dense(read_dense(r)?)
+        };
+        if !quant_input && dict.is_pruned() {
+            return Err(invalid("pruned dictionary without a quantized input matrix"));
+        }
+        args.qout = read_bool(r)?;
+        let output = if quant_input && args.qout {
+            Weights::Quantized(QMatrix::read(r)?)
+        } else {
+            Weights::Dense(read_dense(r)?)
+        };
+        let mut model = FastTextModel::new(args, dict, input, output)?;
+        model.fingerprint = match read_i32(r) {
+            Ok(FINGERPRINT_MAGIC) => Some(read_i64(r)? as u64),
+            _ => None,
+        };
+        Ok(model)
+    }
+
+    fn new(args: TrainArgs, dict: Dictionary, input: Weights, output: Weights) -> io::Result<FastTextModel> {
+        let osz = if args.model == ModelName::Supervised { dict.nlabels() } else { dict.nwords() };
+        if input.cols() != args.dim as usize || output.cols() != args.dim as usize ||
+            output.rows() != osz as usize || input.rows() < dict.nwords() as usize {
+            return Err(invalid("matrix dimensions do not match the model's dictionary"));
+        }
+        let tree = if args.loss == LossName::HierarchicalSoftmax {
+            let kind = if args.model == ModelName::Supervised { EntryType::Label } else { EntryType::Word };
+            Some(HuffmanTree::new(&dict.counts(kind)))
+        } else {
+            None
+        };
+        Ok(FastTextModel { args, dict, input, output, tree, fingerprint: None })
+    }
+
+    /// A model over a dictionary and dense matrices, such as the ones training produces.
+    pub(crate) fn from_parts(args: TrainArgs, dict: Dictionary, input: Matrix, output: Matrix) -> io::Result<FastTextModel> {
+        FastTextModel::new(args, dict, Weights::Dense(input), Weights::Dense(output))
+    }
+
+    /// the model with a training fingerprint, written after its matrices when saved.
+    pub(crate) fn with_fingerprint(mut self, fingerprint: u64) -> FastTextModel {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// An unsupervised model over the given words whose input matrix holds the word rows
+    /// followed by the n-gram buckets, with an empty output matrix. Used for embeddings
+    /// converted from other formats.
+    pub(crate) fn from_embeddings(args: TrainArgs, words: Vec<String>, input: Matrix) -> io::Result<FastTextModel> {
+        let ntokens = words.len() as i64;
+        let entries = words.into_iter()
+            .map(|word| Entry { word, count: 1, entry_type: EntryType::Word, subwords: Vec::new() })
+            .collect::<Vec<_>>();
+        let output = Matrix::new(entries.len(), input.cols(), vec![0.0; entries.len() * input.cols()]);
+        let dict = Dictionary::new(&args, entries, ntokens);
+        FastTextModel::new(args, dict, Weights::Dense(input), Weights::Dense(output))
+    }
+
+    pub(crate) fn dictionary(&self) -> &Dictionary {
+        &self.dict
+    }
+
+    /// the input and output matrices, unless one is quantized.
+    pub(crate) fn dense_matrices(&self) -> Option<(&Matrix, &Matrix)> {
+        match (&self.input, &self.output) {
+            (Weights::Dense(i), Weights::Dense(o)) => Some((i, o)),
+            _ => None,
+        }
+    }
+
+    /// row i of the input matrix, decoded if the model is quantized.
+    pub(crate) fn input_row(&self, i: usize) -> Vec<f32> {
+        let mut v = vec![0.0; self.dim()];
+        self.input.add_row_to(&mut v, i, 1.0);
+        v
+    }
+
+    /// Saves the model in the format the fastText binary reads: a `.bin` model, or a `.ftz`
+    /// model if it is quantized.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    /// Writes the model in the format written by fastText's saveModel.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_i32(w, FASTTEXT_FILEFORMAT_MAGIC_INT32)?;
+        write_i32(w, FASTTEXT_VERSION)?;
+        self.args.write(w)?;
+        self.dict.write(w)?;
+        write_bool(w, self.is_quantized())?;
+        self.input.write(w)?;
+        write_bool(w, self.args.qout)?;
+        self.output.write(w)?;
+        if let Some(f) = self.fingerprint {
+            write_i32(w, FINGERPRINT_MAGIC)?;
+            write_i64(w, f as i64)?;
+        }
+        Ok(())
+    }
+
+    /// Vectors of all the words in the vocabulary, most frequent first, as written by
+    /// `save_vectors` and taken by the `convert` exporters.
+    pub fn word_vectors(&self) -> Vec<(String, Vec<f32>)> {
+        self.dict.entries()[..self.dict.nwords() as usize].iter()
+            .map(|e| (e.word.clone(), self.word_vector(&e.word)))
+            .collect()
+    }
+
+    /// Writes the vectors of all words in the vocabulary to a `.vec` file, as fastText does
+    /// next to every model it trains.
+    pub fn save_vectors<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let words = &self.dict.entries()[..self.dict.nwords() as usize];
+        let mut w = VecWriter::create(path, words.len(), self.dim())?;
+        for e in words {
+            w.write(&e.word, &self.word_vector(&e.word))?;
+        }
+        w.finish().map(|_| ())
+    }
+
+    /// Compresses a supervised model with product quantization and writes it to `output`
+    /// (conventionally a `.ftz` file), which both this crate and the fastText binary can load.
+    ///
+    /// With a `cutoff`, only the EOS token and the `cutoff` words and n-grams with the largest
+    /// vectors are kept. `retrain` then trains the pruned model again on `input`, the training
+    /// data, for the model's number of epochs and learning rate before it is compressed, as
+    /// fastText does.
+    pub fn quantize<P: AsRef<Path>>(&self, qargs: &QuantizeArgs, output: P, input: Option<&Path>) -> io::Result<FastTextModel> {
+        if self.args.model != ModelName::Supervised {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "only supervised models can be quantized"));
+        }
+        if qargs.retrain && input.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "retraining a quantized model needs the training data"));
+        }
+        let (input_matrix, output_matrix) = self.dense_matrices()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "model is already quantized"))?;
+        let mut args = self.args.clone();
+        args.qout = qargs.qout;
+        let mut dict = self.dict.clone();
+
+        let (input_matrix, output_matrix) = if qargs.cutoff > 0 && qargs.cutoff < input_matrix.rows() {
+            let mut idx = self.select_embeddings(input_matrix, qargs.cutoff);
+            dict.prune(&mut idx);
+            let pruned = input_matrix.select_rows(&idx);
+            match input {
+                Some(data) if qargs.retrain => {
+                    let pruned = FastTextModel::from_parts(self.args.clone(), dict.clone(), pruned, output_matrix.clone())?;
+                    let tuned = train::fine_tune(&pruned, data, self.args.epoch.max(1) as u32, self.args.lr, false)?;
+                    let (i, o) = tuned.dense_matrices().expect("fine-tuning gives dense matrices");
+                    (Cow::Owned(i.clone()), Cow::Owned(o.clone()))
+                }
+                _ => (Cow::Owned(pruned), Cow::Borrowed(output_matrix)),
+            }
+        } else {
+            (Cow::Borrowed(input_matrix), Cow::Borrowed(output_matrix))
+        };
+        let qinput = Weights::Quantized(QMatrix::new(&input_matrix, qargs.dsub, qargs.qnorm)?);
+        let qoutput = if qargs.qout {
+            Weights::Quantized(QMatrix::new(&output_matrix, 2, qargs.qnorm)?)
+        } else {
+            Weights::Dense(output_matrix.into_owned())
+        };
+        let model = FastTextModel::new(args, dict, qinput, qoutput)?;
+        model.save(output)?;
+        Ok(model)
+    }
+
+    /// Continues training a supervised model for `epochs` passes over `new_examples` (labeled
+    /// lines, like the training data), with a learning rate decaying linearly from `lr`, and
+    /// writes the result to `output` (a `.bin` file).
+    ///
+    /// With `grow_vocabulary`, words and labels of the examples that the model does not know
+    /// (and that occur minCount and minCountLabel times) are added first: new words get random
+    /// vectors and new labels zero output rows. Models trained with hierarchical softmax cannot
+    /// gain labels, since their tree depends on the label counts.
+    ///
+    /// ```no_run
+    /// # use fast_text::FastTextModel;
+    /// let model = FastTextModel::load("sup.bin").unwrap();
+    /// let tuned = model.fine_tune("today.txt", 5, 0.05, true, "sup_tuned.bin").unwrap();
+    /// ```
+    pub fn fine_tune<P, Q>(&self, new_examples: P, epochs: u32, lr: f64, grow_vocabulary: bool, output: Q) -> io::Result<FastTextModel>
+        where P: AsRef<Path>, Q: AsRef<Path>
+    {
+        let model = train::fine_tune(self, new_examples.as_ref(), epochs, lr, grow_vocabulary)?;
+        model.save(output)?;
+        Ok(model)
+    }
+
+    /// Reduces the vectors to `target_dim` dimensions and writes the smaller model to `output`
+    /// (a `.bin` file), like fastText's `reduce_model`: the input matrix (words and n-gram
+    /// buckets) and the output matrix are projected onto the `target_dim` principal components
+    /// of the input matrix.
+    pub fn reduce_dimensions<P: AsRef<Path>>(&self, target_dim: usize, output: P) -> io::Result<FastTextModel> {
+        let (input, output_matrix) = match (&self.input, &self.output) {
+            (Weights::Dense(i), Weights::Dense(o)) => (i, o),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "quantized models cannot be reduced")),
+        };
+        if target_dim == 0 || target_dim > self.dim() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "cannot reduce {} dimensions to {}", self.dim(), target_dim)));
+        }
+        let pca = Pca::fit(&input.data(), self.dim(), target_dim);
+        let project = |m: &Matrix| {
+            let data = (0..m.rows()).flat_map(|i| pca.project(&m.row(i))).collect();
+            Matrix::new(m.rows(), target_dim, data)
+        };
+        let mut args = self.args.clone();
+        args.dim = target_dim as i32;
+        let model = FastTextModel::new(args, self.dict.clone(), Weights::Dense(project(input)),
+                                       Weights::Dense(project(output_matrix)))?;
+        model.save(output)?;
+        Ok(model)
+    }
+
+    /// Restricts the vocabulary to the words for which `keep(word, count)` is true, and writes
+    /// the smaller model to `output` (a `.bin` file) and its word vectors next to it (the same
+    /// path with a `.vec` extension). The n-gram buckets, and so the vectors of
+    /// out-of-vocabulary words, are unchanged. The EOS token and labels are always kept.
+    ///
+    /// Output rows follow their words with negative sampling; models trained with hierarchical
+    /// softmax get a new, zero output matrix since their tree depends on the whole vocabulary.
+    pub fn prune_vocabulary<F, P>(&self, keep: F, output: P) -> io::Result<FastTextModel>
+        where F: Fn(&str, u64) -> bool, P: AsRef<Path>
+    {
+        let words = &self.dict.entries()[..self.dict.nwords() as usize];
+        let ids = words.iter().enumerate()
+            .filter(|&(_, e)| e.word == EOS || keep(&e.word, e.count as u64))
+            .map(|(i, _)| i as i32)
+            .collect();
+        self.prune_words(ids, output.as_ref())
+    }
+
+    /// Like `prune_vocabulary`, keeping the n most frequent words.
+    pub fn prune_vocabulary_top_n<P: AsRef<Path>>(&self, n: usize, output: P) -> io::Result<FastTextModel> {
+        let words = &self.dict.entries()[..self.dict.nwords() as usize];
+        let mut ids: Vec<i32> = (0..words.len() as i32).collect();
+        ids.sort_by_key(|&i| (words[i as usize].word != EOS, -words[i as usize].count, i));
+        ids.truncate(n.max(1));
+        ids.sort();
+        self.prune_words(ids, output.as_ref())
+    }
+
+    /// keeps the words with the given sorted ids.
+    fn prune_words(&self, ids: Vec<i32>, output: &Path) -> io::Result<FastTextModel> {
+        let (input, output_matrix) = match (&self.input, &self.output) {
+            (Weights::Dense(i), Weights::Dense(o)) => (i, o),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "quantized models cannot be pruned")),
+        };
+        let nwords = self.dict.nwords();
+        let entries = self.dict.entries();
+        let kept = ids.iter().map(|&i| &entries[i as usize])
+            .chain(&entries[nwords as usize..])
+            .map(|e| Entry { subwords: Vec::new(), ..e.clone() })
+            .collect();
+        let dict = Dictionary::new(&self.args, kept, self.dict.ntokens());
+
+        let rows: Vec<i32> = ids.iter().cloned().chain(nwords..input.rows() as i32).collect();
+        let input = input.select_rows(&rows);
+        let output_matrix = match (self.args.model, self.args.loss) {
+            (ModelName::Supervised, _) => output_matrix.clone(),
+            (_, LossName::HierarchicalSoftmax) => Matrix::new(ids.len(), self.dim(), vec![0.0; ids.len() * self.dim()]),
+            _ => output_matrix.select_rows(&ids),
+        };
+        let model = FastTextModel::new(self.args.clone(), dict, Weights::Dense(input), Weights::Dense(output_matrix))?;
+        model.save(output)?;
+        model.save_vectors(output.with_extension("vec"))?;
+        Ok(model)
+    }
+
+    /// the EOS row, then the cutoff - 1 rows with the largest norms.
+    fn select_embeddings(&self, input: &Matrix, cutoff: usize) -> Vec<i32> {
+        let norms = input.row_norms();
+        let eos = self.dict.get_id(EOS);
+        let mut idx: Vec<i32> = (0..input.rows() as i32).collect();
+        idx.sort_by(|&a, &b| (Some(b) == eos).cmp(&(Some(a) == eos))
+            .then(norms[b as usize].partial_cmp(&norms[a as usize]).unwrap_or(Ordering::Equal)));
+        idx.truncate(cutoff);
+        idx
+    }
+
+    /// Words of the vocabulary, most frequent first (as fastText sorts them), without labels.
+    pub fn words(&self) -> Vec<&str> {
+        self.dict.entries()[..self.dict.nwords() as usize].iter().map(|e| e.word.as_str()).collect()
+    }
+
+    /// Labels of a supervised model, most frequent first.
+    pub fn labels(&self) -> Vec<&str> {
+        self.dict.entries()[self.dict.nwords() as usize..].iter().map(|e| e.word.as_str()).collect()
+    }
+
+    /// Number of times a word of the vocabulary occurred in the training data.
+    pub fn word_frequency(&self, word: &str) -> Option<u64> {
+        self.word_id(word).map(|i| self.dict.entries()[i].count as u64)
+    }
+
+    /// Every label with the number of training examples it occurred in, most frequent first.
+    pub fn label_counts(&self) -> Vec<(&str, u64)> {
+        self.dict.entries()[self.dict.nwords() as usize..].iter()
+            .map(|e| (e.word.as_str(), e.count as u64))
+            .collect()
+    }
+
+    /// Whether a word or label is in the model's dictionary.
+    pub fn contains(&self, word: &str) -> bool {
+        self.dict.get_id(word).is_some()
+    }
+
+    /// Index of a word in the vocabulary (and of its row in the input matrix); None for labels
+    /// and out-of-vocabulary words.
+    pub fn word_id(&self, word: &str) -> Option<usize> {
+        self.dict.get_id(word).filter(|&i| i < self.dict.nwords()).map(|i| i as usize)
+    }
+
+    /// Whether the model was loaded from a quantized (`.ftz`) file.
+    pub fn is_quantized(&self) -> bool {
+        match self.input {
+            Weights::Quantized(_) => true,
+            Weights::Dense(_) => false,
+        }
+    }
+
+    /// Whether the dense matrices are views of a memory-mapped file (see `load_mmap`).
+    pub fn is_mapped(&self) -> bool {
+        match self.input {
+            Weights::Dense(ref m) => m.is_mapped(),
+            Weights::Quantized(_) => false,
+        }
+    }
+
+    /// The settings the model was trained with, as saved in the model file. Settings fastText
+    /// does not save (such as `lr` and `thread`) have their default values.
+    pub fn args(&self) -> &TrainArgs {
+        &self.args
+    }
+
+    /// Hash of the training data and arguments of a model trained with
+    /// `Trainer::reproducible`; None for other models.
+    pub fn fingerprint(&self) -> Option<u64> {
+        self.fingerprint
+    }
+
+    /// Size of the word vectors.
+    pub fn dim(&self) -> usize {
+        self.args.dim as usize
+    }
+
+    /// average of the given input rows.
+    fn average_rows(&self, rows: &[i32]) -> Vec<f32> {
+        let mut v = vec![0.0; self.dim()];
+        for &r in rows {
+            self.input.add_row_to(&mut v, r as usize, 1.0);
+        }
+        if !rows.is_empty() {
+            scale(&mut v, 1.0 / rows.len() as f32);
+        }
+        v
+    }
+
+    /// Vector for a word, built from its character n-grams if it is out of vocabulary. Matches
+    /// `print-word-vectors`.
+    pub fn word_vector(&self, word: &str) -> Vec<f32> {
+        self.average_rows(&self.dict.get_subwords(word))
+    }
+
+    /// The parts a word's vector is built from, as fastText's `print-ngrams` lists them: the
+    /// word itself if it is in the vocabulary (with no bucket), then its character n-grams with
+    /// their buckets. Out-of-vocabulary words are composed purely from n-grams, so the first
+    /// entry has a bucket. N-grams dropped by quantization are left out.
+    pub fn subwords(&self, word: &str) -> Vec<(String, Option<u32>)> {
+        let mut out = Vec::new();
+        if self.word_id(word).is_some() {
+            out.push((word.to_string(), None));
+        }
+        out.extend(self.dict.get_ngrams(word).into_iter()
+            .filter(|n| n.2.is_some())
+            .map(|(ngram, bucket, _)| (ngram, Some(bucket))));
+        out
+    }
+
+    /// The vectors of the parts listed by `subwords`, in the same order. `word_vector` is
+    /// their average.
+    pub fn subword_vectors(&self, word: &str) -> Vec<(String, Vec<f32>)> {
+        let mut out = Vec::new();
+        if let Some(i) = self.word_id(word) {
+            out.push((word.to_string(), self.input_row(i)));
+        }
+        out.extend(self.dict.get_ngrams(word).into_iter()
+            .filter_map(|(ngram, _, row)| row.map(|r| (ngram, self.input_row(r as usize)))));
+        out
+    }
+
+    /// Vector for a line of text. Matches `print-sentence-vectors`: supervised models average
+    /// the rows of every token and word n-gram, other models average the normalized vectors of
+    /// the words.
+    pub fn sentence_vector(&self, sentence: &str) -> Vec<f32> {
+        if self.args.model == ModelName::Supervised {
+            let (words, _) = self.dict.get_line(sentence);
+            return self.average_rows(&words);
+        }
+        let mut svec = vec![0.0; self.dim()];
+        let mut count = 0;
+        for word in sentence.split_whitespace() {
+            let mut v = self.word_vector(word);
+            let n = norm(&v);
+            if n > 0.0 {
+                scale(&mut v, 1.0 / n);
+                for (s, x) in svec.iter_mut().zip(v) {
+                    *s += x;
+                }
+                count += 1;
+            }
+        }
+        if count > 0 {
+            scale(&mut svec, 1.0 / count as f32);
+        }
+        svec
+    }
+
+    /// Top k labels for a line of text with their probabilities, most likely first. Matches
+    /// `predict-prob` on a supervised model; empty if no token of the text is known.
+    pub fn predict(&self, text: &str, k: usize) -> Vec<(String, f32)> {
+        let (words, _) = self.dict.get_line(text);
+        if words.is_empty() || k == 0 {
+            return Vec::new();
+        }
+        let hidden = self.average_rows(&words);
+        let mut scores: Vec<(f32, i32)> = match self.tree {
+            Some(ref tree) => {
+                let mut heap = Vec::new();
+                self.dfs(tree, k, tree.root(), 0.0, &mut heap, &hidden);
+                heap
+            }
+            None => self.output_log_probs(&hidden).into_iter().enumerate()
+                .map(|(i, p)| (p, i as i32)).collect(),
+        };
+        scores.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal).then(a.1.cmp(&b.1)));
+        scores.truncate(k);
+        scores.into_iter().map(|(p, i)| (self.dict.get_label(i).to_string(), p.exp())).collect()
+    }
+
+    /// P@k and R@k on a labeled test file, computed like fastText's `test` command without
+    /// running it. Lines without known labels or without any input row are skipped.
+    pub fn test<P: AsRef<Path>>(&self, test_data: P, k: u32) -> io::Result<Evaluation> {
+        let (mut n, mut correct, mut predicted, mut nlabels) = (0u64, 0u64, 0u64, 0u64);
+        for line in BufReader::new(File::open(test_data)?).lines() {
+            let line = line?;
+            let (words, labels) = self.dict.get_line(&line);
+            if labels.is_empty() || words.is_empty() {
+                continue;
+            }
+            n += 1;
+            nlabels += labels.len() as u64;
+            // fewer than k predictions when the model has fewer labels
+            let predictions = self.predict(&line, k as usize);
+            predicted += predictions.len() as u64;
+            correct += predictions.iter()
+                .filter(|p| self.dict.get_id(&p.0).is_some_and(|i| labels.contains(&(i - self.dict.nwords()))))
+                .count() as u64;
+        }
+        let ratio = |a: u64, b: u64| if b == 0 { 0.0 } else { a as f64 / b as f64 };
+        Ok(Evaluation { n, k, precision: ratio(correct, predicted), recall: ratio(correct, nlabels) })
+    }
+
+    /// The row of the output matrix for a label of a supervised model: the label's embedding in
+    /// the space of sentence vectors. None for unknown labels and for models trained with
+    /// hierarchical softmax, whose output rows belong to the nodes of a tree rather than labels.
+    pub fn label_vector(&self, label: &str) -> Option<Vec<f32>> {
+        if self.args.model != ModelName::Supervised || self.tree.is_some() {
+            return None;
+        }
+        let id = self.dict.get_id(label).filter(|&i| i >= self.dict.nwords())?;
+        let mut v = vec![0.0; self.dim()];
+        self.output.add_row_to(&mut v, (id - self.dict.nwords()) as usize, 1.0);
+        Some(v)
+    }
+
+    /// Cosine similarity of two labels' vectors (see `label_vector`).
+    pub fn label_similarity(&self, a: &str, b: &str) -> Option<f32> {
+        Some(cosine(&self.label_vector(a)?, &self.label_vector(b)?))
+    }
+
+    /// The k labels whose vectors are most similar (by cosine) to a text's sentence vector or
+    /// to a vector, most similar first. Empty for models without label vectors.
+    ///
+    /// ```no_run
+    /// # use fast_text::FastTextModel;
+    /// let model = FastTextModel::load("sup.bin").unwrap();
+    /// let close = model.nearest_labels("how to thicken a sauce", 5);
+    /// let v = model.label_vector("__label__sauce").unwrap();
+    /// let overlapping = model.nearest_labels(&v, 5);
+    /// ```
+    pub fn nearest_labels<'a, Q: Into<LabelQuery<'a>>>(&self, query: Q, k: usize) -> Vec<(String, f32)> {
+        let v = match query.into() {
+            LabelQuery::Text(text) => self.sentence_vector(text),
+            LabelQuery::Vector(v) => v.to_vec(),
+        };
+        let mut out: Vec<(String, f32)> = self.labels().into_iter()
+            .filter_map(|l| self.label_vector(l).map(|lv| (l.to_string(), cosine(&v, &lv))))
+            .collect();
+        out.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        out.truncate(k);
+        out
+    }
+
+    /// The k words whose vectors are most similar (by cosine) to a word's vector, most similar
+    /// first and leaving out the word itself. Matches `nn`.
+    pub fn nearest_neighbors(&self, word: &str, k: usize) -> Vec<(String, f32)> {
+        self.nearest_neighbors_in(&self.unit_word_vectors(), word, k)
+    }
+
+    /// The vectors of the words in the vocabulary scaled to unit length (zero vectors stay
+    /// zero), one row per word, as fastText precomputes them for `nn`.
+    pub(crate) fn unit_word_vectors(&self) -> Matrix {
+        let words = self.words();
+        let mut data = Vec::with_capacity(words.len() * self.dim());
+        for w in &words {
+            let mut v = self.word_vector(w);
+            let n = norm(&v);
+            if n > 0.0 {
+                scale(&mut v, 1.0 / n);
+            }
+            data.extend(v);
+        }
+        Matrix::new(words.len(), self.dim(), data)
+    }
+
+    /// `nearest_neighbors` with vectors from `unit_word_vectors`, which can then be kept for
+    /// the next query.
+    pub(crate) fn nearest_neighbors_in(&self, vectors: &Matrix, word: &str, k: usize) -> Vec<(String, f32)> {
+        let query = self.word_vector(word);
+        let n = norm(&query);
+        let mut out: Vec<(String, f32)> = self.words().into_iter().enumerate()
+            .filter(|&(_, w)| w != word)
+            .map(|(i, w)| (w.to_string(), if n == 0.0 { 0.0 } else { vectors.dot_row(&query, i) / n }))
+            .collect();
+        out.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        out.truncate(k);
+        out
+    }
+
+    /// log probability of every label given the hidden layer.
+    fn output_log_probs(&self, hidden: &[f32]) -> Vec<f32> {
+        let mut out: Vec<f32> = (0..self.output.rows()).map(|i| self.output.dot_row(hidden, i)).collect();
+        if self.args.loss == LossName::OneVsAll {
+            return out.into_iter().map(|x| std_log(table_sigmoid(x))).collect();
+        }
+        let max = out.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let mut z = 0.0;
+        for x in out.iter_mut() {
+            *x = (*x - max).exp();
+            z += *x;
+        }
+        out.into_iter().map(|x| std_log(x / z)).collect()
+    }
+
+    /// best k leaves of the hierarchical softmax tree below node.
+    fn dfs(&self, tree: &HuffmanTree, k: usize, node: usize, score: f32, heap: &mut Vec<(f32, i32)>, hidden: &[f32]) {
+        if heap.len() == k && score < heap.iter().map(|p| p.0).fold(f32::INFINITY, f32::min) {
+            return;
+        }
+        if tree.is_leaf(node) {
+            heap.push((score, node as i32));
+            if heap.len() > k {
+                let worst = (0..heap.len()).min_by(|&a, &b| heap[a].0.partial_cmp(&heap[b].0).unwrap()).unwrap();
+                heap.swap_remove(worst);
+            }
+            return;
+        }
+        // unclamped, unlike the sigmoid of training
+        let f = self.output.dot_row(hidden, tree.output_row(node));
+        let f = (1.0 / f64::from(1.0 + (-f).exp())) as f32;
+        self.dfs(tree, k, tree.left(node), score + std_log(1.0 - f), heap, hidden);
+        self.dfs(tree, k, tree.right(node), score + std_log(f), heap, hidden);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use std::path::PathBuf;
+
+    use rng::MinstdRand;
+    use vec_file::read_vec;
+    use super::*;
+
+    /// serializes a small supervised model: words </s>, good and bad, labels pos and neg.
+    fn model_bytes(quantized: bool) -> Vec<u8> {
+        let mut b = Vec::new();
+        let i32s = |b: &mut Vec<u8>, vs: &[i32]| for v in vs {
+            b.extend_from_slice(&v.to_le_bytes());
+        };
+        i32s(&mut b, &[FASTTEXT_FILEFORMAT_MAGIC_INT32, FASTTEXT_VERSION]);
+        // dim ws epoch minCount neg wordNgrams loss(softmax) model(sup) bucket minn maxn lrUpdateRate
+        i32s(&mut b, &[2, 5, 5, 1, 5, 1, 3, 3, 4, 0, 0, 100]);
+        b.extend_from_slice(&1e-4f64.to_le_bytes());
+        i32s(&mut b, &[5, 3, 2]);
+        b.extend_from_slice(&5i64.to_le_bytes());
+        b.extend_from_slice(&(-1i64).to_le_bytes());
+        for &(w, c, t) in &[("</s>", 2i64, 0u8), ("good", 1, 0), ("bad", 1, 0),
+                            ("__label__pos", 1, 1), ("__label__neg", 1, 1)] {
+            b.extend_from_slice(w.as_bytes());
+            b.push(0);
+            b.extend_from_slice(&c.to_le_bytes());
+            b.push(t);
+        }
+        let f32s = |b: &mut Vec<u8>, vs: &[f32]| for v in vs {
+            b.extend_from_slice(&v.to_le_bytes());
+        };
+        let dense = |b: &mut Vec<u8>, m: i64, vs: &[f32]| {
+            b.extend_from_slice(&m.to_le_bytes());
+            b.extend_from_slice(&2i64.to_le_bytes());
+            f32s(b, vs);
+        };
+        // centroids 0..5 of a single sub-quantizer of two dimensions
+        let mut centroids = vec![0.0; 2 * 256];
+        centroids[..10].copy_from_slice(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 5.0, 0.0, 0.0, 5.0]);
+        let quant = |b: &mut Vec<u8>, m: i64, codes: &[u8]| {
+            b.push(0); // qnorm
+            b.extend_from_slice(&m.to_le_bytes());
+            b.extend_from_slice(&2i64.to_le_bytes());
+            b.extend_from_slice(&(codes.len() as i32).to_le_bytes());
+            b.extend_from_slice(codes);
+            for v in &[2i32, 1, 2, 2] {
+                b.extend_from_slice(&v.to_le_bytes());
+            }
+            f32s(b, &centroids);
+        };
+        b.push(quantized as u8);
+        if quantized {
+            quant(&mut b, 7, &[0, 1, 2, 0, 0, 0, 0]);
+        } else {
+            dense(&mut b, 7, &[0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        }
+        b.push(quantized as u8); // qout
+        if quantized {
+            quant(&mut b, 2, &[3, 4]);
+        } else {
+            dense(&mut b, 2, &[5.0, 0.0, 0.0, 5.0]);
+        }
+        b
+    }
+
+    #[test]
+    fn test_read_and_predict() {
+        for &quantized in &[false, true] {
+            let m = FastTextModel::read(&mut &model_bytes(quantized)[..]).unwrap();
+            assert_eq!(m.is_quantized(), quantized);
+            assert_eq!(m.dim(), 2);
+            assert_eq!(m.word_vector("good"), vec![1.0, 0.0]);
+            assert_eq!(m.word_vector("unknown"), vec![0.0, 0.0]);
+            // good and </s> are averaged
+            assert_eq!(m.sentence_vector("good"), vec![0.5, 0.0]);
+
+            let p = m.predict("good", 2);
+            assert_eq!(p.len(), 2);
+            assert_eq!(p[0].0, "__label__pos");
+            let expected = 2.5f32.exp() / (2.5f32.exp() + 1.0);
+            assert!((p[0].1 - expected).abs() < 1e-4);
+            assert_eq!(m.predict("bad", 1)[0].0, "__label__neg");
+            assert_eq!(m.predict("bad", 1).len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        for &quantized in &[false, true] {
+            let b = model_bytes(quantized);
+            let m = FastTextModel::read(&mut &b[..]).unwrap();
+            let mut out = Vec::new();
+            m.write(&mut out).unwrap();
+            assert_eq!(out, b);
+        }
+
+        let m = random_model(3);
+        let path = temp_path("save.bin");
+        m.save(&path).unwrap();
+        let loaded = FastTextModel::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.word_vector("w3"), m.word_vector("w3"));
+        assert_eq!(loaded.predict("w3 w4", 3), m.predict("w3 w4", 3));
+    }
+
+    #[test]
+    fn test_load_mmap() {
+        let m = random_model(3);
+        let path = temp_path("mmap.bin");
+        m.save(&path).unwrap();
+        let mapped = FastTextModel::load_mmap(&path).unwrap();
+        assert!(mapped.is_mapped() && !m.is_mapped());
+        assert_eq!(mapped.word_vector("w3"), m.word_vector("w3"));
+        assert_eq!(mapped.sentence_vector("w3 w4 oov"), m.sentence_vector("w3 w4 oov"));
+        assert_eq!(mapped.predict("w3 w4", 3), m.predict("w3 w4", 3));
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        mapped.write(&mut a).unwrap();
+        m.write(&mut b).unwrap();
+        assert_eq!(a, b);
+
+        let len = fs::metadata(&path).unwrap().len();
+        fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 4).unwrap();
+        assert!(FastTextModel::load_mmap(&path).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_path("mmap.ftz");
+        fs::write(&path, model_bytes(true)).unwrap();
+        let q = FastTextModel::load_mmap(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(q.is_quantized() && !q.is_mapped());
+        assert_eq!(q.word_vector("good"), vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_reduce_dimensions() {
+        let m = random_model(3);
+        let path = temp_path("reduced.bin");
+        let r = m.reduce_dimensions(2, &path).unwrap();
+        let loaded = FastTextModel::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((r.dim(), loaded.dim()), (2, 2));
+        assert_eq!(loaded.word_vector("w5"), r.word_vector("w5"));
+        assert_eq!(loaded.word_vector("oov").len(), 2);
+        assert_eq!(loaded.predict("w1 w2", 3).len(), 3);
+
+        // keeping every dimension is a rotation: dot products and norms are unchanged
+        let full = m.reduce_dimensions(4, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!((norm(&full.word_vector("w5")) - norm(&m.word_vector("w5"))).abs() < 1e-5);
+        let (p, q) = (full.predict("w1 w2", 3), m.predict("w1 w2", 3));
+        for (a, b) in p.iter().zip(&q) {
+            assert_eq!(a.0, b.0);
+            assert!((a.1 - b.1).abs() < 1e-4);
+        }
+        assert!(m.reduce_dimensions(5, &path).is_err());
+    }
+
+    #[test]
+    fn test_prune_vocabulary() {
+        let args = TrainArgs { dim: 4, bucket: 50, minn: 2, maxn: 3, ..TrainArgs::default() };
+        let words: Vec<String> = vec![EOS, "cheese", "sauce", "pie", "cake"].into_iter().map(String::from).collect();
+        let mut rng = MinstdRand::new(5);
+        let data = (0..55 * 4).map(|_| rng.uniform() as f32 - 0.5).collect();
+        let m = FastTextModel::from_embeddings(args, words, Matrix::new(55, 4, data)).unwrap();
+
+        let path = temp_path("pruned.bin");
+        let p = m.prune_vocabulary(|w, _| w.starts_with('c'), &path).unwrap();
+        let loaded = FastTextModel::load(&path).unwrap();
+        let vectors = read_vec(path.with_extension("vec"), None).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("vec")).unwrap();
+
+        let kept: Vec<_> = vectors.iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(kept, vec![EOS, "cheese", "cake"]);
+        assert_eq!(p.dictionary().nwords(), 3);
+        assert_eq!(loaded.word_vector("cake"), m.word_vector("cake"));
+        // pruned words become out of vocabulary but keep their n-grams
+        let mut expected = vec![0.0; 4];
+        let ngrams = m.dictionary().get_subwords("sauce");
+        for &r in &ngrams[1..] {
+            m.input.add_row_to(&mut expected, r as usize, 1.0 / (ngrams.len() - 1) as f32);
+        }
+        assert_close(&loaded.word_vector("sauce"), &expected, 1e-6);
+
+        let top = m.prune_vocabulary_top_n(2, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("vec")).unwrap();
+        let kept: Vec<_> = top.dictionary().entries().iter().map(|e| e.word.as_str()).collect();
+        assert_eq!(kept, vec![EOS, "cheese"]);
+
+        // supervised models keep their labels and output matrix
+        let s = random_model(3).prune_vocabulary(|w, _| w == "w1", &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("vec")).unwrap();
+        assert_eq!((s.dictionary().nwords(), s.dictionary().nlabels()), (2, 3));
+        assert_eq!(s.predict("w1", 3).len(), 3);
+    }
+
+    #[test]
+    fn test_vocabulary() {
+        let m = FastTextModel::read(&mut &model_bytes(false)[..]).unwrap();
+        assert_eq!(m.words(), vec![EOS, "good", "bad"]);
+        assert_eq!(m.labels(), vec!["__label__pos", "__label__neg"]);
+        assert_eq!(m.word_frequency(EOS), Some(2));
+        assert_eq!(m.word_frequency("bad"), Some(1));
+        assert_eq!(m.word_frequency("__label__pos"), None);
+        assert_eq!(m.label_counts(), vec![("__label__pos", 1), ("__label__neg", 1)]);
+        assert!(m.contains("good") && m.contains("__label__neg") && !m.contains("ugly"));
+        assert_eq!(m.word_id("bad"), Some(2));
+        assert_eq!(m.word_id("__label__pos"), None);
+    }
+
+    #[test]
+    fn test_args() {
+        let m = FastTextModel::read(&mut &model_bytes(false)[..]).unwrap();
+        let args = m.args();
+        assert_eq!((args.dim, args.ws, args.epoch, args.min_count, args.neg), (2, 5, 5, 1, 5));
+        assert_eq!((args.loss, args.model, args.bucket, args.minn, args.maxn), (LossName::Softmax, ModelName::Supervised, 4, 0, 0));
+        assert_eq!(args.model.command(), "supervised");
+        let cli = random_model(2).args().cli_args();
+        assert_eq!(cli["loss"], "softmax");
+        assert_eq!(cli["wordNgrams"], "2");
+        assert_eq!(cli["bucket"], "300");
+        assert_eq!(cli["t"], "0.0001");
+    }
+
+    #[test]
+    fn test_subwords() {
+        let args = TrainArgs { dim: 4, bucket: 50, minn: 3, maxn: 3, ..TrainArgs::default() };
+        let words: Vec<String> = vec![EOS, "cheese"].into_iter().map(String::from).collect();
+        let mut rng = MinstdRand::new(5);
+        let data = (0..52 * 4).map(|_| rng.uniform() as f32 - 0.5).collect();
+        let m = FastTextModel::from_embeddings(args, words, Matrix::new(52, 4, data)).unwrap();
+
+        let s = m.subwords("cheese");
+        assert_eq!(s[0], ("cheese".to_string(), None));
+        let names: Vec<_> = s[1..].iter().map(|p| p.0.as_str()).collect();
+        assert_eq!(names, vec!["<ch", "che", "hee", "ees", "ese", "se>"]);
+        assert!(s[1..].iter().all(|p| p.1.is_some_and(|b| b < 50)));
+        let oov = m.subwords("cheesy");
+        assert!(oov[0].1.is_some());
+        assert_eq!(oov.len(), 6);
+        assert_eq!(m.subwords(EOS), vec![(EOS.to_string(), None)]);
+
+        for w in &["cheese", "cheesy"] {
+            let vectors = m.subword_vectors(w);
+            let mut avg = vec![0.0; 4];
+            for (_, v) in &vectors {
+                for (a, x) in avg.iter_mut().zip(v) {
+                    *a += x / vectors.len() as f32;
+                }
+            }
+            assert_close(&avg, &m.word_vector(w), 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_label_vectors() {
+        for &quantized in &[false, true] {
+            let m = FastTextModel::read(&mut &model_bytes(quantized)[..]).unwrap();
+            assert_eq!(m.label_vector("__label__pos"), Some(vec![5.0, 0.0]));
+            assert_eq!(m.label_vector("__label__neg"), Some(vec![0.0, 5.0]));
+            assert_eq!(m.label_vector("good"), None);
+            assert_eq!(m.label_vector("__label__unknown"), None);
+            assert_eq!(m.label_similarity("__label__pos", "__label__neg"), Some(0.0));
+            assert_eq!(m.label_similarity("__label__pos", "__label__pos"), Some(1.0));
+
+            let nearest = m.nearest_labels("bad", 2);
+            assert_eq!(nearest[0].0, "__label__neg");
+            assert!((nearest[0].1 - 1.0).abs() < 1e-6);
+            let v = vec![1.0, 0.2];
+            assert_eq!(m.nearest_labels(&v, 1)[0].0, "__label__pos");
+            assert_eq!(m.nearest_labels(&v[..], 5).len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_nearest_neighbors() {
+        let m = random_model(2);
+        let nn = m.nearest_neighbors("w3", 5);
+        assert_eq!(nn.len(), 5);
+        assert!(nn.iter().all(|n| n.0 != "w3"));
+        assert!(nn.windows(2).all(|p| p[0].1 >= p[1].1));
+        let best = m.words().into_iter().filter(|&w| w != "w3")
+            .map(|w| cosine(&m.word_vector("w3"), &m.word_vector(w)))
+            .fold(f32::NEG_INFINITY, f32::max);
+        assert!((nn[0].1 - best).abs() < 1e-6);
+        assert_eq!(m.nearest_neighbors("w3", 100).len(), m.words().len() - 1);
+        let vectors = m.unit_word_vectors();
+        assert_eq!(m.nearest_neighbors_in(&vectors, "w3", 5), nn);
+        assert_eq!(m.nearest_neighbors_in(&vectors, "w4", 5), m.nearest_neighbors("w4", 5));
+    }
+
+    #[test]
+    fn test_save_vectors() {
+        let m = random_model(3);
+        let path = temp_path("save.vec");
+        m.save_vectors(&path).unwrap();
+        let entries = read_vec(&path, None).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 21);
+        assert_eq!(entries[0].0, EOS);
+        assert_eq!(entries[4].0, "w3");
+        assert_close(&entries[4].1, &m.word_vector("w3"), 1e-4);
+        assert_eq!(m.word_vectors()[4], ("w3".to_string(), m.word_vector("w3")));
+    }
+
+    #[test]
+    fn test_read_rejects_other_files() {
+        let e = FastTextModel::read(&mut &b"not a model at all"[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let mut b = model_bytes(false);
+        b.truncate(b.len() - 4);
+        assert!(FastTextModel::read(&mut &b[..]).is_err());
+        // a matrix header claiming more values than the file holds, or than fit in memory
+        for &(m, n) in &[(1i64 << 40, 1i64 << 20), (i64::MAX, 2)] {
+            let mut h = Vec::new();
+            write_i64(&mut h, m).unwrap();
+            write_i64(&mut h, n).unwrap();
+            h.extend_from_slice(&[0; 64]);
+            assert!(Matrix::read(&mut &h[..]).is_err());
+        }
+    }
+
+    /// a supervised model with random weights, 20 words, 300 buckets and nlabels labels.
+    fn random_model(nlabels: usize) -> FastTextModel {
+        let args = TrainArgs {
+            dim: 4,
+            model: ModelName::Supervised,
+            loss: LossName::Softmax,
+            word_ngrams: 2,
+            bucket: 300,
+            minn: 0,
+            maxn: 0,
+            ..TrainArgs::default()
+        };
+        let mut words = vec![EOS.to_string()];
+        words.extend((0..20).map(|i| format!("w{}", i)));
+        let nwords = words.len();
+        words.extend((0..nlabels).map(|i| format!("__label__{}", i)));
+        let entries = words.into_iter().enumerate().map(|(i, word)| Entry {
+            word,
+            count: 100 - (i % 50) as i64,
+            entry_type: if i < nwords { EntryType::Word } else { EntryType::Label },
+            subwords: Vec::new(),
+        }).collect();
+        let dict = Dictionary::new(&args, entries, 1000);
+        let mut rng = MinstdRand::new(7);
+        let mut matrix = |m: usize| {
+            let mut b = Vec::new();
+            write_i64(&mut b, m as i64).unwrap();
+            write_i64(&mut b, 4).unwrap();
+            let data: Vec<f32> = (0..m * 4).map(|_| rng.uniform() as f32 - 0.5).collect();
+            write_f32s(&mut b, &data).unwrap();
+            Matrix::read(&mut &b[..]).unwrap()
+        };
+        let input = Weights::Dense(matrix(nwords + 300));
+        let output = Weights::Dense(matrix(nlabels));
+        FastTextModel::new(args, dict, input, output).unwrap()
+    }
+
+    #[test]
+    fn test_precision_counts_predictions() {
+        let m = random_model(2);
+        let path = temp_path("precision.txt");
+        fs::write(&path, "w1 w2 __label__0 __label__1\nw3 __label__0\n").unwrap();
+        // two labels: two predictions a line however large k is, three of them correct
+        let e = m.test(&path, 5).unwrap();
+        assert_eq!(e.n, 2);
+        assert_eq!(e.precision, 0.75);
+        assert_eq!(e.recall, 1.0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("fast_text_{}_{}", process::id(), name))
+    }
+
+    fn assert_close(a: &[f32], b: &[f32], tol: f32) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < tol, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_quantize() {
+        let m = random_model(3);
+        let path = temp_path("quantize.ftz");
+        let q = m.quantize(&QuantizeArgs::default(), &path, None).unwrap();
+        let loaded = FastTextModel::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(q.is_quantized() && loaded.is_quantized());
+        for w in &["w0", "w7", "w19", "oov"] {
+            assert_close(&m.word_vector(w), &loaded.word_vector(w), 0.05);
+            assert_eq!(q.word_vector(w), loaded.word_vector(w));
+        }
+        let text = "w1 w2 w3 w4";
+        assert_close(&m.sentence_vector(text), &loaded.sentence_vector(text), 0.05);
+        assert_eq!(m.predict(text, 3).len(), loaded.predict(text, 3).len());
+    }
+
+    #[test]
+    fn test_quantize_cutoff() {
+        let m = random_model(3);
+        let path = temp_path("cutoff.ftz");
+        let args = QuantizeArgs { cutoff: 260, qnorm: true, ..QuantizeArgs::default() };
+        m.quantize(&args, &path, None).unwrap();
+        let loaded = FastTextModel::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded.dict.is_pruned());
+        assert_eq!(loaded.input.rows(), 260);
+        assert!(loaded.dict.get_id(EOS).is_some());
+        assert_eq!(loaded.dict.nlabels(), 3);
+        // retained words keep (approximately) their vectors
+        for i in 0..20 {
+            let w = format!("w{}", i);
+            if loaded.dict.get_id(&w).is_some() {
+                assert_close(&m.word_vector(&w), &loaded.word_vector(&w), 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantize_retrain() {
+        let m = random_model(2);
+        let (path, data) = (temp_path("retrain.ftz"), temp_path("retrain.txt"));
+        let args = QuantizeArgs { cutoff: 260, retrain: true, ..QuantizeArgs::default() };
+        let e = m.quantize(&args, &path, None).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+        fs::write(&data, "w1 w2 __label__0\nw3 w4 __label__1\n".repeat(200)).unwrap();
+        let q = m.quantize(&args, &path, Some(&data)).unwrap();
+        let loaded = FastTextModel::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&data).unwrap();
+        assert!(loaded.dict.is_pruned());
+        assert_eq!(loaded.input.rows(), 260);
+        // the pruned model learned the training data before it was compressed
+        let p = q.predict("w1 w2", 1);
+        assert!(p[0].0 == "__label__0" && p[0].1 > 0.8, "{:?}", p);
+        assert_eq!(q.predict("w3 w4", 1)[0].0, "__label__1");
+        assert_eq!(loaded.predict("w3 w4", 1), q.predict("w3 w4", 1));
+    }
+
+    #[test]
+    fn test_quantize_output() {
+        let path = temp_path("qout.ftz");
+        let args = QuantizeArgs { qout: true, ..QuantizeArgs::default() };
+        // the output matrix needs at least 256 rows
+        assert!(random_model(3).quantize(&args, &path, None).is_err());
+
+        let m = random_model(300);
+        m.quantize(&args, &path, None).unwrap();
+        let loaded = FastTextModel::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.args.qout);
+        let p = loaded.predict("w1 w2", 5);
+        assert_eq!(p.len(), 5);
+        assert!(p[0].1 >= p[4].1);
+    }
+}
@@ -0,0 +1,144 @@
+//! Principal component analysis of embedding matrices, computed like fastText's
+//! `reduce_model`: the components are the leading eigenvectors of the covariance matrix, and
+//! rows are projected onto them without centering, so averages of rows (word and sentence
+//! vectors) project to the averages of the projected rows.
+
+/// The leading principal components of a set of vectors.
+#[derive(Debug, Clone)]
+pub struct Pca {
+    dim: usize,
+    /// k rows of dim values, largest variance first.
+    components: Vec<Vec<f64>>,
+}
+
+impl Pca {
+    /// fits k components to the rows of the row-major data with dim columns.
+    pub fn fit(data: &[f32], dim: usize, k: usize) -> Pca {
+        assert!(k <= dim, "cannot keep more components than dimensions");
+        let rows = data.len() / dim.max(1);
+        let mut mean = vec![0.0f64; dim];
+        for row in data.chunks_exact(dim) {
+            for (m, &x) in mean.iter_mut().zip(row) {
+                *m += x as f64;
+            }
+        }
+        for m in mean.iter_mut() {
+            *m /= rows.max(1) as f64;
+        }
+        let mut cov = vec![0.0f64; dim * dim];
+        let mut centered = vec![0.0f64; dim];
+        for row in data.chunks_exact(dim) {
+            for (c, (&x, m)) in centered.iter_mut().zip(row.iter().zip(&mean)) {
+                *c = x as f64 - m;
+            }
+            for i in 0..dim {
+                let ci = centered[i];
+                for j in i..dim {
+                    cov[i * dim + j] += ci * centered[j];
+                }
+            }
+        }
+        let denom = rows.saturating_sub(1).max(1) as f64;
+        for i in 0..dim {
+            for j in i..dim {
+                cov[i * dim + j] /= denom;
+                cov[j * dim + i] = cov[i * dim + j];
+            }
+        }
+        let (values, vectors) = symmetric_eigen(cov, dim);
+        let mut order: Vec<usize> = (0..dim).collect();
+        order.sort_by(|&a, &b| values[b].partial_cmp(&values[a]).unwrap());
+        let components = order.into_iter().take(k)
+            .map(|c| (0..dim).map(|i| vectors[i * dim + c]).collect())
+            .collect();
+        Pca { dim, components }
+    }
+
+    /// coordinates of x along each component.
+    pub fn project(&self, x: &[f32]) -> Vec<f32> {
+        debug_assert_eq!(x.len(), self.dim);
+        self.components.iter()
+            .map(|c| c.iter().zip(x).map(|(a, &b)| a * b as f64).sum::<f64>() as f32)
+            .collect()
+    }
+}
+
+/// eigenvalues and eigenvectors (as the columns of a row-major n x n matrix) of a symmetric
+/// matrix, by cyclic Jacobi rotations.
+fn symmetric_eigen(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut v = vec![0.0; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.0;
+    }
+    let total: f64 = a.iter().map(|x| x * x).sum();
+    for _ in 0..100 {
+        let off: f64 = (0..n).flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i * n + j] * a[i * n + j]).sum();
+        if off <= 1e-22 * total || off == 0.0 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i * n + i]).collect(), v)
+}
+
+#[cfg(test)]
+mod tests {
+    use rng::MinstdRand;
+    use super::*;
+
+    #[test]
+    fn test_eigen() {
+        let (values, vectors) = symmetric_eigen(vec![2.0, 1.0, 1.0, 2.0], 2);
+        let mut sorted = values.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!((sorted[0] - 1.0).abs() < 1e-9 && (sorted[1] - 3.0).abs() < 1e-9);
+        let big = if values[0] > values[1] { 0 } else { 1 };
+        assert!((vectors[big].abs() - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((vectors[2 + big] - vectors[big]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fit() {
+        // points spread along (3, 4) / 5 with a little noise along (-4, 3) / 5 and z
+        let mut rng = MinstdRand::new(11);
+        let mut data = Vec::new();
+        for _ in 0..500 {
+            let (a, b, c) = (rng.uniform() * 10.0 - 5.0, rng.uniform() * 0.2 - 0.1, rng.uniform() * 0.02);
+            data.extend_from_slice(&[(0.6 * a - 0.8 * b) as f32, (0.8 * a + 0.6 * b) as f32, c as f32]);
+        }
+        let pca = Pca::fit(&data, 3, 2);
+        let p = pca.project(&[3.0, 4.0, 0.0]);
+        assert_eq!(p.len(), 2);
+        assert!((p[0].abs() - 5.0).abs() < 0.01, "{:?}", p);
+        assert!(p[1].abs() < 0.1, "{:?}", p);
+        let q = pca.project(&[-4.0, 3.0, 0.0]);
+        assert!((q[1].abs() - 5.0).abs() < 0.01, "{:?}", q);
+    }
+}
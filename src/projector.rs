@@ -0,0 +1,106 @@
+//! Export of a model's most frequent words for the TensorBoard embedding projector.
+//!
+//! ```no_run
+//! use fast_text::FastTextModel;
+//! use fast_text::projector;
+//!
+//! let model = FastTextModel::load("sample.bin").unwrap();
+//! // the 10000 most frequent words, reduced to 50 dimensions
+//! projector::export_projector(&model, "projector", 10000, Some(50)).unwrap();
+//! ```
+//!
+//! The directory can then be opened with `tensorboard --logdir projector`.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use args::ModelName;
+use model::FastTextModel;
+use pca::Pca;
+use vec_file::format_g;
+
+/// Writes `vectors.tsv`, `metadata.tsv` and `projector_config.pbtxt` for the `top_n` most
+/// frequent words of the model to `dir`, creating it if needed.
+///
+/// The metadata lists each word with its frequency and, for supervised models, the label the
+/// model predicts for it. With `pca_dim`, the vectors are first reduced to that many principal
+/// components.
+pub fn export_projector<P: AsRef<Path>>(model: &FastTextModel, dir: P, top_n: usize, pca_dim: Option<usize>) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    let dict = model.dictionary();
+    let words = &dict.entries()[..(dict.nwords() as usize).min(top_n)];
+    let mut vectors: Vec<Vec<f32>> = words.iter().map(|e| model.word_vector(&e.word)).collect();
+    if let Some(k) = pca_dim {
+        if k == 0 || k > model.dim() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "cannot reduce {} dimensions to {}", model.dim(), k)));
+        }
+        let data: Vec<f32> = vectors.iter().flat_map(|v| v.iter().cloned()).collect();
+        let pca = Pca::fit(&data, model.dim(), k);
+        vectors = vectors.iter().map(|v| pca.project(v)).collect();
+    }
+
+    let mut w = BufWriter::new(File::create(dir.join("vectors.tsv"))?);
+    for v in &vectors {
+        let values: Vec<String> = v.iter().map(|&x| format_g(x, 5)).collect();
+        writeln!(w, "{}", values.join("\t"))?;
+    }
+    w.flush()?;
+
+    let supervised = model.args().model == ModelName::Supervised;
+    let mut w = BufWriter::new(File::create(dir.join("metadata.tsv"))?);
+    writeln!(w, "word\tfrequency{}", if supervised { "\tlabel" } else { "" })?;
+    for e in words {
+        write!(w, "{}\t{}", e.word, e.count)?;
+        if supervised {
+            let label = model.predict(&e.word, 1).into_iter().next().map_or(String::new(), |p| p.0);
+            write!(w, "\t{}", label)?;
+        }
+        writeln!(w)?;
+    }
+    w.flush()?;
+
+    let mut w = File::create(dir.join("projector_config.pbtxt"))?;
+    writeln!(w, "embeddings {{\n  tensor_name: \"fasttext\"\n  tensor_path: \"vectors.tsv\"\n  metadata_path: \"metadata.tsv\"\n}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use args::TrainArgs;
+    use matrix::Matrix;
+    use rng::MinstdRand;
+    use super::*;
+
+    #[test]
+    fn test_export_projector() {
+        let args = TrainArgs { dim: 4, minn: 0, maxn: 0, bucket: 0, ..TrainArgs::default() };
+        let words: Vec<String> = (0..10).map(|i| format!("w{}", i)).collect();
+        let mut rng = MinstdRand::new(5);
+        let data = (0..40).map(|_| rng.uniform() as f32 - 0.5).collect();
+        let model = FastTextModel::from_embeddings(args, words, Matrix::new(10, 4, data)).unwrap();
+        let dir = env::temp_dir().join(format!("fast_text_{}_projector", process::id()));
+
+        export_projector(&model, &dir, 6, None).unwrap();
+        let vectors = fs::read_to_string(dir.join("vectors.tsv")).unwrap();
+        let lines: Vec<&str> = vectors.lines().collect();
+        assert_eq!(lines.len(), 6);
+        let first: Vec<f32> = lines[0].split('\t').map(|x| x.parse().unwrap()).collect();
+        let expected = model.word_vector("w0");
+        assert!(first.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-4));
+        let metadata = fs::read_to_string(dir.join("metadata.tsv")).unwrap();
+        assert!(metadata.starts_with("word\tfrequency\nw0\t1\nw1\t1\n"));
+        assert_eq!(metadata.lines().count(), 7);
+        assert!(fs::read_to_string(dir.join("projector_config.pbtxt")).unwrap().contains("tensor_path: \"vectors.tsv\""));
+
+        export_projector(&model, &dir, 100, Some(2)).unwrap();
+        let vectors = fs::read_to_string(dir.join("vectors.tsv")).unwrap();
+        assert_eq!(vectors.lines().count(), 10);
+        assert!(vectors.lines().all(|l| l.split('\t').count() == 2));
+        assert!(export_projector(&model, &dir, 100, Some(5)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+//! Product-quantized matrices, used by `.ftz` models produced by `quantize`.
+//!
+//! Each row is split into sub-vectors of `dsub` dimensions and every sub-vector is stored as a
+//! one byte index into a table of 256 centroids. With `qnorm`, rows are normalized before
+//! quantization and their norms are quantized separately with a one dimensional quantizer.
+
+use std::io::{self, Read, Write};
+
+use bin_io::*;
+use matrix::Matrix;
+use rng::MinstdRand;
+
+/// number of centroids per sub-quantizer.
+pub const KSUB: usize = 256;
+/// at most this many points per centroid are sampled to train a sub-quantizer.
+const MAX_POINTS_PER_CLUSTER: usize = 256;
+const NITER: usize = 25;
+const EPS: f32 = 1e-7;
+const SEED: u64 = 1234;
+
+fn dist_l2(x: &[f32], y: &[f32]) -> f32 {
+    x.iter().zip(y).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductQuantizer {
+    dim: usize,
+    nsubq: usize,
+    dsub: usize,
+    lastdsub: usize,
+    centroids: Vec<f32>,
+}
+
+impl ProductQuantizer {
+    /// an untrained quantizer splitting dim dimensions into sub-vectors of dsub dimensions
+    /// (the last one may be shorter).
+    pub fn new(dim: usize, dsub: usize) -> ProductQuantizer {
+        let (nsubq, lastdsub) = if dim.is_multiple_of(dsub) { (dim / dsub, dsub) } else { (dim / dsub + 1, dim % dsub) };
+        ProductQuantizer { dim, nsubq, dsub, lastdsub, centroids: vec![0.0; dim * KSUB] }
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<ProductQuantizer> {
+        let dim = read_i32(r)?;
+        let nsubq = read_i32(r)?;
+        let dsub = read_i32(r)?;
+        let lastdsub = read_i32(r)?;
+        if dim < 0 || nsubq <= 0 || dsub <= 0 || lastdsub <= 0 {
+            return Err(invalid("invalid product quantizer in model file"));
+        }
+        let len = (dim as usize).checked_mul(KSUB).ok_or_else(|| invalid("product quantizer too large"))?;
+        let centroids = read_f32s(r, len)?;
+        Ok(ProductQuantizer {
+            dim: dim as usize,
+            nsubq: nsubq as usize,
+            dsub: dsub as usize,
+            lastdsub: lastdsub as usize,
+            centroids,
+        })
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_i32(w, self.dim as i32)?;
+        write_i32(w, self.nsubq as i32)?;
+        write_i32(w, self.dsub as i32)?;
+        write_i32(w, self.lastdsub as i32)?;
+        write_f32s(w, &self.centroids)
+    }
+
+    fn subq_dim(&self, m: usize) -> usize {
+        if m == self.nsubq - 1 { self.lastdsub } else { self.dsub }
+    }
+
+    /// the centroids of sub-quantizer m, KSUB of them back to back.
+    fn centroids_mut(&mut self, m: usize) -> &mut [f32] {
+        let start = m * KSUB * self.dsub;
+        let d = self.subq_dim(m);
+        &mut self.centroids[start..start + KSUB * d]
+    }
+
+    /// Learns the centroids of every sub-quantizer with k-means on (a sample of) the n rows
+    /// of x.
+    pub fn train(&mut self, x: &[f32]) -> io::Result<()> {
+        let n = x.len() / self.dim;
+        if n < KSUB {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "matrix too small for quantization: it has {} rows but must have at least {}", n, KSUB)));
+        }
+        let mut rng = MinstdRand::new(SEED);
+        let mut perm: Vec<usize> = (0..n).collect();
+        let np = n.min(MAX_POINTS_PER_CLUSTER * KSUB);
+        for m in 0..self.nsubq {
+            let d = self.subq_dim(m);
+            if np != n {
+                rng.shuffle(&mut perm);
+            }
+            let mut xslice = Vec::with_capacity(np * d);
+            for &p in perm.iter().take(np) {
+                let start = p * self.dim + m * self.dsub;
+                xslice.extend_from_slice(&x[start..start + d]);
+            }
+            kmeans(&xslice, self.centroids_mut(m), d, &mut rng);
+        }
+        Ok(())
+    }
+
+    /// index of the closest of the KSUB centroids in c to x.
+    fn assign_centroid(x: &[f32], c: &[f32]) -> u8 {
+        let d = x.len();
+        let mut code = 0;
+        let mut dis = dist_l2(x, &c[..d]);
+        for j in 1..KSUB {
+            let disj = dist_l2(x, &c[j * d..(j + 1) * d]);
+            if disj < dis {
+                code = j as u8;
+                dis = disj;
+            }
+        }
+        code
+    }
+
+    /// codes of the rows of x, nsubq bytes per row.
+    pub fn compute_codes(&self, x: &[f32]) -> Vec<u8> {
+        let mut codes = Vec::with_capacity(x.len() / self.dim * self.nsubq);
+        for row in x.chunks(self.dim) {
+            for m in 0..self.nsubq {
+                let d = self.subq_dim(m);
+                let start = m * KSUB * self.dsub;
+                let c = &self.centroids[start..start + KSUB * d];
+                codes.push(ProductQuantizer::assign_centroid(&row[m * self.dsub..m * self.dsub + d], c));
+            }
+        }
+        codes
+    }
+
+    /// centroid i of sub-quantizer m.
+    fn centroid(&self, m: usize, i: u8) -> &[f32] {
+        if m == self.nsubq - 1 {
+            let start = m * KSUB * self.dsub + i as usize * self.lastdsub;
+            &self.centroids[start..start + self.lastdsub]
+        } else {
+            let start = (m * KSUB + i as usize) * self.dsub;
+            &self.centroids[start..start + self.dsub]
+        }
+    }
+
+    /// adds `alpha` times the decoded row to x.
+    fn add_code(&self, x: &mut [f32], code: &[u8], alpha: f32) {
+        for (m, &c) in code.iter().enumerate() {
+            let start = m * self.dsub;
+            for (xj, cj) in x[start..].iter_mut().zip(self.centroid(m, c)) {
+                *xj += alpha * cj;
+            }
+        }
+    }
+
+    /// dot product of x with the decoded row, times `alpha`.
+    fn mul_code(&self, x: &[f32], code: &[u8], alpha: f32) -> f32 {
+        let mut res = 0.0;
+        for (m, &c) in code.iter().enumerate() {
+            let start = m * self.dsub;
+            for (xj, cj) in x[start..].iter().zip(self.centroid(m, c)) {
+                res += xj * cj;
+            }
+        }
+        res * alpha
+    }
+}
+
+/// k-means with KSUB centroids over the points of dimension d in x, writing the centroids to c.
+fn kmeans(x: &[f32], c: &mut [f32], d: usize, rng: &mut MinstdRand) {
+    let n = x.len() / d;
+    let mut perm: Vec<usize> = (0..n).collect();
+    rng.shuffle(&mut perm);
+    for i in 0..KSUB {
+        c[i * d..(i + 1) * d].copy_from_slice(&x[perm[i] * d..(perm[i] + 1) * d]);
+    }
+    let mut codes = vec![0u8; n];
+    for _ in 0..NITER {
+        // E step
+        for (code, p) in codes.iter_mut().zip(x.chunks(d)) {
+            *code = ProductQuantizer::assign_centroid(p, c);
+        }
+        // M step
+        let mut nelts = vec![0usize; KSUB];
+        for v in c.iter_mut() {
+            *v = 0.0;
+        }
+        for (&k, p) in codes.iter().zip(x.chunks(d)) {
+            for (cj, xj) in c[k as usize * d..].iter_mut().zip(p) {
+                *cj += xj;
+            }
+            nelts[k as usize] += 1;
+        }
+        for k in 0..KSUB {
+            if nelts[k] != 0 {
+                for cj in c[k * d..(k + 1) * d].iter_mut() {
+                    *cj /= nelts[k] as f32;
+                }
+            }
+        }
+        // split a large cluster, chosen at random in proportion to its size, for every empty one
+        for k in 0..KSUB {
+            if nelts[k] == 0 {
+                let mut m = 0;
+                while rng.uniform() * (n - KSUB) as f64 >= nelts[m] as f64 - 1.0 {
+                    m = (m + 1) % KSUB;
+                }
+                c.copy_within(m * d..(m + 1) * d, k * d);
+                for j in 0..d {
+                    let sign = if j % 2 == 1 { EPS } else { -EPS };
+                    c[k * d + j] += sign;
+                    c[m * d + j] -= sign;
+                }
+                nelts[k] = nelts[m] / 2;
+                nelts[m] -= nelts[k];
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QMatrix {
+    qnorm: bool,
+    m: usize,
+    n: usize,
+    codes: Vec<u8>,
+    pq: ProductQuantizer,
+    norm_codes: Vec<u8>,
+    npq: Option<ProductQuantizer>,
+}
+
+impl QMatrix {
+    /// Quantizes a matrix. With qnorm, rows are normalized first and their norms quantized
+    /// separately.
+    pub fn new(mat: &Matrix, dsub: usize, qnorm: bool) -> io::Result<QMatrix> {
+        let (m, n) = (mat.rows(), mat.cols());
+        let mut pq = ProductQuantizer::new(n, dsub);
+        let (data, norm_codes, npq) = if qnorm {
+            let norms = mat.row_norms();
+            let mut data = mat.data().to_vec();
+            for (row, &norm) in data.chunks_mut(n).zip(norms.iter()) {
+                if norm != 0.0 {
+                    for x in row.iter_mut() {
+                        *x /= norm;
+                    }
+                }
+            }
+            let mut npq = ProductQuantizer::new(1, 1);
+            npq.train(&norms)?;
+            let norm_codes = npq.compute_codes(&norms);
+            (data, norm_codes, Some(npq))
+        } else {
+            (mat.data().to_vec(), Vec::new(), None)
+        };
+        pq.train(&data)?;
+        let codes = pq.compute_codes(&data);
+        Ok(QMatrix { qnorm, m, n, codes, pq, norm_codes, npq })
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<QMatrix> {
+        let qnorm = read_bool(r)?;
+        let m = read_size(r)?;
+        let n = read_size(r)?;
+        let codesize = read_i32(r)?;
+        if codesize < 0 {
+            return Err(invalid("negative code size in model file"));
+        }
+        let codes = read_bytes(r, codesize as usize)?;
+        let pq = ProductQuantizer::read(r)?;
+        if pq.dim != n || codes.len() != m * pq.nsubq {
+            return Err(invalid("quantized matrix does not match its quantizer"));
+        }
+        let (norm_codes, npq) = if qnorm {
+            let norm_codes = read_bytes(r, m)?;
+            (norm_codes, Some(ProductQuantizer::read(r)?))
+        } else {
+            (Vec::new(), None)
+        };
+        Ok(QMatrix { qnorm, m, n, codes, pq, norm_codes, npq })
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_bool(w, self.qnorm)?;
+        write_i64(w, self.m as i64)?;
+        write_i64(w, self.n as i64)?;
+        write_i32(w, self.codes.len() as i32)?;
+        w.write_all(&self.codes)?;
+        self.pq.write(w)?;
+        if let Some(ref npq) = self.npq {
+            w.write_all(&self.norm_codes)?;
+            npq.write(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.m
+    }
+
+    pub fn cols(&self) -> usize {
+        self.n
+    }
+
+    fn norm(&self, i: usize) -> f32 {
+        match self.npq {
+            Some(ref npq) => npq.centroid(0, self.norm_codes[i])[0],
+            None => 1.0,
+        }
+    }
+
+    fn code(&self, i: usize) -> &[u8] {
+        let nsubq = self.pq.nsubq;
+        &self.codes[i * nsubq..(i + 1) * nsubq]
+    }
+
+    /// adds `a` times the decoded row i to x.
+    pub fn add_row_to(&self, x: &mut [f32], i: usize, a: f32) {
+        self.pq.add_code(x, self.code(i), a * self.norm(i));
+    }
+
+    pub fn dot_row(&self, x: &[f32], i: usize) -> f32 {
+        self.pq.mul_code(x, self.code(i), self.norm(i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a 2x3 matrix with dsub 2: one full and one partial sub-quantizer.
+    fn qmatrix(qnorm: bool) -> QMatrix {
+        let mut centroids = vec![0.0; 3 * KSUB];
+        // sub-quantizer 0, centroid 1 and 2
+        centroids[2] = 1.0;
+        centroids[3] = 2.0;
+        centroids[4] = 3.0;
+        centroids[5] = 4.0;
+        // sub-quantizer 1 (lastdsub 1), centroid 1
+        centroids[2 * KSUB + 1] = 5.0;
+        let pq = ProductQuantizer { dim: 3, nsubq: 2, dsub: 2, lastdsub: 1, centroids };
+        let mut norms = vec![0.0; KSUB];
+        norms[1] = 2.0;
+        let npq = ProductQuantizer { dim: 1, nsubq: 1, dsub: 1, lastdsub: 1, centroids: norms };
+        QMatrix {
+            qnorm,
+            m: 2,
+            n: 3,
+            codes: vec![1, 1, 2, 0],
+            pq,
+            norm_codes: if qnorm { vec![1, 0] } else { Vec::new() },
+            npq: if qnorm { Some(npq) } else { None },
+        }
+    }
+
+    fn row(q: &QMatrix, i: usize) -> Vec<f32> {
+        let mut x = vec![0.0; q.cols()];
+        q.add_row_to(&mut x, i, 1.0);
+        x
+    }
+
+    #[test]
+    fn test_decode() {
+        let q = qmatrix(false);
+        assert_eq!(row(&q, 0), vec![1.0, 2.0, 5.0]);
+        assert_eq!(row(&q, 1), vec![3.0, 4.0, 0.0]);
+        assert_eq!(q.dot_row(&[1.0, 1.0, 1.0], 0), 8.0);
+    }
+
+    #[test]
+    fn test_decode_qnorm() {
+        let q = qmatrix(true);
+        assert_eq!(row(&q, 0), vec![2.0, 4.0, 10.0]);
+        assert_eq!(row(&q, 1), vec![0.0, 0.0, 0.0]);
+        assert_eq!(q.dot_row(&[1.0, 0.0, 1.0], 0), 12.0);
+    }
+}
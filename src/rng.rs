@@ -0,0 +1,59 @@
+//! The minimal standard generator (C++'s `std::minstd_rand`) that fastText seeds its training
+//! and quantization with.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinstdRand {
+    state: u64,
+}
+
+const MODULUS: u64 = 2147483647;
+
+impl MinstdRand {
+    pub fn new(seed: u64) -> MinstdRand {
+        let state = seed % MODULUS;
+        MinstdRand { state: if state == 0 { 1 } else { state } }
+    }
+
+    /// the current state; `MinstdRand::new(state)` continues the same sequence.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// next value, in [1, 2^31 - 2].
+    pub fn next_u32(&mut self) -> u32 {
+        self.state = self.state * 48271 % MODULUS;
+        self.state as u32
+    }
+
+    /// uniform in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u32() - 1) as f64 / (MODULUS - 1) as f64
+    }
+
+    /// uniform in [0, n).
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.uniform() * n as f64) as usize).min(n - 1)
+    }
+
+    pub fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            let j = self.below(i + 1);
+            v.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minstd() {
+        // the 10000th value of a default-seeded minstd_rand, as given by the C++ standard
+        let mut r = MinstdRand::new(1);
+        for _ in 0..9999 {
+            r.next_u32();
+        }
+        assert_eq!(r.next_u32(), 399268537);
+    }
+}
@@ -0,0 +1,1155 @@
+//! Native training of fastText models, so `supervised`, `skipgram` and `cbow` do not need
+//! the fastText binary.
+//!
+//! ```no_run
+//! use fast_text::{ModelName, TrainArgs, Trainer};
+//!
+//! let args = TrainArgs { epoch: 25, word_ngrams: 2, ..TrainArgs::supervised() };
+//! let model = Trainer::new(args).train("sample_text.txt").unwrap();
+//! model.save("sup.bin").unwrap();
+//!
+//! let args = TrainArgs { model: ModelName::Cbow, ..TrainArgs::default() };
+//! let model = Trainer::new(args).train("sample_text.txt").unwrap();
+//! model.save("cbow.bin").unwrap();
+//! model.save_vectors("cbow.vec").unwrap();
+//! ```
+//!
+//! Long runs can write checkpoints and be resumed from them after an interruption:
+//!
+//! ```no_run
+//! use fast_text::{TrainArgs, Trainer};
+//!
+//! let trainer = match Trainer::resume_from("run.ckpt") {
+//!     Ok(trainer) => trainer,
+//!     Err(_) => Trainer::new(TrainArgs::default()),
+//! };
+//! let model = trainer.checkpoint("run.ckpt", 10_000_000).train("corpus.txt").unwrap();
+//! ```
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use args::{LossName, ModelName, TrainArgs};
+use bin_io::*;
+use dictionary::{count_entries, tokenize, Dictionary, EntryType};
+use huffman::HuffmanTree;
+use matrix::Matrix;
+use model::{sigmoid, std_log, FastTextModel, FASTTEXT_FILEFORMAT_MAGIC_INT32, FASTTEXT_VERSION};
+use rng::MinstdRand;
+
+/// size of the table negatives are drawn from.
+const NEGATIVE_TABLE_SIZE: usize = 10_000_000;
+/// longest run of words unsupervised training looks at as one sentence.
+const MAX_LINE_SIZE: usize = 1024;
+/// marks the training state that follows the model in a checkpoint file.
+const CHECKPOINT_MAGIC: i32 = 0x4654434b;
+
+/// A matrix that training threads update without locks, like fastText's Hogwild SGD. Values
+/// are f32 bits in relaxed atomics: concurrent updates of a row may overwrite each other,
+/// which SGD tolerates, without making the races undefined behaviour.
+struct SharedMatrix {
+    n: usize,
+    data: Vec<AtomicU32>,
+}
+
+impl SharedMatrix {
+    /// a m x n matrix filled with the given values, row after row.
+    fn new<I: Iterator<Item = f32>>(m: usize, n: usize, values: I) -> SharedMatrix {
+        let data: Vec<AtomicU32> = values.take(m * n).map(|x| AtomicU32::new(x.to_bits())).collect();
+        assert_eq!(data.len(), m * n, "matrix data does not have m x n values");
+        SharedMatrix { n, data }
+    }
+
+    fn get(&self, k: usize) -> f32 {
+        f32::from_bits(self.data[k].load(Ordering::Relaxed))
+    }
+
+    /// adds `a` times row i to x.
+    fn add_row_to(&self, x: &mut [f32], i: usize, a: f32) {
+        for (j, xj) in x.iter_mut().enumerate() {
+            *xj += a * self.get(i * self.n + j);
+        }
+    }
+
+    fn dot_row(&self, x: &[f32], i: usize) -> f32 {
+        x.iter().enumerate().map(|(j, xj)| xj * self.get(i * self.n + j)).sum()
+    }
+
+    /// adds `a` times x to row i.
+    fn add_to_row(&self, i: usize, x: &[f32], a: f32) {
+        for (j, xj) in x.iter().enumerate() {
+            let k = i * self.n + j;
+            self.data[k].store((self.get(k) + a * xj).to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// writes the matrix the way `Matrix::write` does.
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_i64(w, (self.data.len() / self.n.max(1)) as i64)?;
+        write_i64(w, self.n as i64)?;
+        for k in 0..self.data.len() {
+            w.write_all(&self.get(k).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// sets row i to x.
+    fn set_row(&self, i: usize, x: &[f32]) {
+        for (j, xj) in x.iter().enumerate() {
+            self.data[i * self.n + j].store(xj.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// a copy of the current values.
+    fn to_matrix(&self) -> Matrix {
+        Matrix::new(self.data.len() / self.n.max(1), self.n, (0..self.data.len()).map(|k| self.get(k)).collect())
+    }
+
+    fn into_matrix(self) -> Matrix {
+        let m = self.data.len() / self.n.max(1);
+        Matrix::new(m, self.n, self.data.into_iter().map(|x| f32::from_bits(x.into_inner())).collect())
+    }
+}
+
+/// Trains models with the settings of a `TrainArgs`, the way the fastText binary does:
+/// `args.thread` threads each read their own part of the corpus and update shared matrices,
+/// with a learning rate decaying linearly from `args.lr` to 0 over `args.epoch` passes.
+#[derive(Debug, Clone)]
+pub struct Trainer {
+    args: TrainArgs,
+    /// where to write checkpoints, and every how many tokens
+    checkpoint: Option<(PathBuf, u64)>,
+    resume: Option<Box<Checkpoint>>,
+    validation: Option<PathBuf>,
+    patience: Option<usize>,
+    pretrained: Option<PathBuf>,
+    /// the seed of reproducible training
+    seed: Option<u64>,
+}
+
+/// Scores of a supervised model on the validation file after an epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochMetrics {
+    /// 1 for the first epoch
+    pub epoch: u32,
+    /// average negative log-likelihood of the examples' labels
+    pub loss: f64,
+    /// P@1
+    pub precision: f64,
+    /// R@1
+    pub recall: f64,
+    /// harmonic mean of P@1 and R@1
+    pub f1: f64,
+}
+
+/// What happened during training.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrainingSummary {
+    /// words whose vectors were initialised from the `pretrained` model
+    pub words_carried_over: usize,
+    /// validation scores after each epoch that was trained
+    pub epochs: Vec<EpochMetrics>,
+    /// the epoch whose model was returned: the one with the best F1
+    pub best_epoch: Option<u32>,
+    /// whether training stopped before `args.epoch` epochs because F1 stopped improving
+    pub stopped_early: bool,
+}
+
+/// A training run as saved in a checkpoint file: the file starts with the model in `.bin`
+/// format, so it can also be loaded as a model, followed by the training state.
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    dict: Dictionary,
+    input: Matrix,
+    output: Matrix,
+    /// size of the training file, to detect resuming on another corpus
+    input_size: u64,
+    token_count: u64,
+    seed: Option<u64>,
+    workers: Vec<WorkerState>,
+}
+
+/// Where a training thread is, so it can continue from there.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct WorkerState {
+    /// offset of the next line in the training file
+    pos: u64,
+    rng: u64,
+    negpos: u64,
+    /// tokens processed but not yet added to the shared count
+    local: u64,
+}
+
+/// State shared by the training threads.
+struct Training<'a> {
+    args: &'a TrainArgs,
+    dict: &'a Dictionary,
+    checkpoint: Option<&'a (PathBuf, u64)>,
+    input_size: u64,
+    /// tokens in one pass over the training file
+    epoch_tokens: u64,
+    /// seed of reproducible training; random choices are derived from 0 otherwise
+    seed: Option<u64>,
+    workers: Vec<Mutex<WorkerState>>,
+    input: SharedMatrix,
+    output: SharedMatrix,
+    /// rows of the output matrix: labels for supervised models, words otherwise
+    osz: usize,
+    tree: Option<HuffmanTree>,
+    negatives: Vec<u32>,
+    /// probability of keeping each word in unsupervised training
+    pkeep: Vec<f32>,
+    /// tokens processed by all threads, for the learning rate schedule
+    token_count: AtomicU64,
+}
+
+/// The buffers of one training thread (fastText's per-thread Model).
+struct Worker<'a> {
+    t: &'a Training<'a>,
+    hidden: Vec<f32>,
+    grad: Vec<f32>,
+    scores: Vec<f32>,
+    rng: MinstdRand,
+    negpos: usize,
+}
+
+impl Trainer {
+    pub fn new(args: TrainArgs) -> Trainer {
+        Trainer { args, checkpoint: None, resume: None, validation: None, patience: None, pretrained: None, seed: None }
+    }
+
+    /// Evaluates a supervised model on a labeled validation file after every epoch. Training
+    /// then returns the model of the epoch with the best F1 at 1 rather than the last one.
+    /// Checkpoints do not keep the validation scores, so a resumed run cannot be validated.
+    pub fn validation<P: Into<PathBuf>>(mut self, path: P) -> Trainer {
+        self.validation = Some(path.into());
+        self
+    }
+
+    /// Stops training once F1 on the validation file has not improved for `patience` epochs
+    /// in a row. Only used with `validation`.
+    pub fn early_stopping(mut self, patience: usize) -> Trainer {
+        self.patience = Some(patience.max(1));
+        self
+    }
+
+    /// Makes training write its state to `path` each time another `interval` tokens have been
+    /// processed. The file is replaced atomically, so an interrupted write leaves the previous
+    /// checkpoint intact.
+    pub fn checkpoint<P: Into<PathBuf>>(mut self, path: P, interval: u64) -> Trainer {
+        self.checkpoint = Some((path.into(), interval.max(1)));
+        self
+    }
+
+    /// Initialises training from the model at `path` (a `.bin` file) instead of random vectors:
+    /// words both models know start with their previous input vectors, and so do the n-gram
+    /// buckets if both models hash n-grams the same way. The models must have the same
+    /// dimension. Keeps embeddings trained on successive corpora close to each other.
+    pub fn pretrained<P: Into<PathBuf>>(mut self, path: P) -> Trainer {
+        self.pretrained = Some(path.into());
+        self
+    }
+
+    /// Makes training deterministic: it runs on a single thread and draws every random choice
+    /// from `seed`, so the same data and arguments always give the same model. The model
+    /// carries a fingerprint of both (see `FastTextModel::fingerprint`), saved with it.
+    pub fn reproducible(mut self, seed: u64) -> Trainer {
+        self.args.thread = 1;
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Retrains on `input` and checks that the result is, byte for byte, the model saved at
+    /// `model`. Only for reproducible trainers.
+    ///
+    /// ```no_run
+    /// use fast_text::{TrainArgs, Trainer};
+    ///
+    /// let trainer = Trainer::new(TrainArgs::supervised()).reproducible(42);
+    /// trainer.train("train.txt").unwrap().save("model.bin").unwrap();
+    /// assert!(trainer.verify_reproducible("train.txt", "model.bin").unwrap());
+    /// ```
+    pub fn verify_reproducible<P: AsRef<Path>, Q: AsRef<Path>>(&self, input: P, model: Q) -> io::Result<bool> {
+        if self.seed.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the trainer is not reproducible"));
+        }
+        let mut retrained = Vec::new();
+        self.train(input)?.write(&mut retrained)?;
+        Ok(retrained == fs::read(model)?)
+    }
+
+    /// A trainer that continues the run saved in a checkpoint when `train` is called with the
+    /// same training file. It uses the checkpoint's arguments and number of threads; with a
+    /// single thread, the result is the same as that of an uninterrupted run. Training fails if
+    /// a validation file is also set.
+    pub fn resume_from<P: AsRef<Path>>(checkpoint: P) -> io::Result<Trainer> {
+        let (args, c) = read_checkpoint(&mut BufReader::new(File::open(checkpoint)?))?;
+        Ok(Trainer { seed: c.seed, resume: Some(Box::new(c)), ..Trainer::new(args) })
+    }
+
+    /// Builds the dictionary of the corpus at `input` and trains a model on it. Supervised
+    /// corpora have one example per line, with labels marked by `args.label`; skipgram and
+    /// cbow models learn word vectors from plain text, with character n-grams of lengths
+    /// `args.minn` to `args.maxn`.
+    pub fn train<P: AsRef<Path>>(&self, input: P) -> io::Result<FastTextModel> {
+        self.train_with_summary(input).map(|(model, _)| model)
+    }
+
+    /// Trains like `train`, also returning the validation scores of every epoch; the summary
+    /// is empty without a validation file.
+    pub fn train_with_summary<P: AsRef<Path>>(&self, input: P) -> io::Result<(FastTextModel, TrainingSummary)> {
+        let input = input.as_ref();
+        let args = &self.args;
+        if args.dim <= 0 || args.epoch <= 0 || args.thread <= 0 || args.ws <= 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "dim, epoch, thread and ws must be positive"));
+        }
+        if self.validation.is_some() && self.resume.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a resumed run cannot be validated"));
+        }
+        if self.validation.is_some() && args.model != ModelName::Supervised {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "only supervised models can be validated"));
+        }
+        if args.loss == LossName::OneVsAll && args.model != ModelName::Supervised {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the one-vs-all loss is only for supervised models"));
+        }
+        let input_size = fs::metadata(input)?.len();
+        let dict = match self.resume {
+            Some(ref c) if c.input_size != input_size =>
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "the training file is not the one the checkpoint was made with")),
+            Some(ref c) => c.dict.clone(),
+            None => Dictionary::build(args, BufReader::new(File::open(input)?))?,
+        };
+        let supervised = args.model == ModelName::Supervised;
+        if supervised && dict.nlabels() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no labels in the training data"));
+        }
+        let dim = args.dim as usize;
+        let rows = dict.nwords() as usize + args.bucket.max(0) as usize;
+        let seed = self.seed.unwrap_or(0);
+        let fingerprint = match self.seed {
+            Some(seed) => Some(fingerprint(input, args, seed)?),
+            None => None,
+        };
+        let mut rng = MinstdRand::new(seed + 1);
+        let a = 1.0 / dim as f64;
+        let init = (0..).map(|_| (rng.uniform() * 2.0 * a - a) as f32);
+        let osz = if supervised { dict.nlabels() } else { dict.nwords() } as usize;
+        let nthreads = args.thread as usize;
+        let (input_matrix, output_matrix, token_count, workers) = match self.resume {
+            Some(ref c) if c.input.rows() != rows || c.input.cols() != dim ||
+                c.output.rows() != osz || c.output.cols() != dim =>
+                return Err(invalid("checkpoint matrices do not match its dictionary and arguments")),
+            Some(ref c) => (SharedMatrix::new(rows, dim, c.input.data().iter().cloned()),
+                            SharedMatrix::new(osz, dim, c.output.data().iter().cloned()),
+                            c.token_count, c.workers.clone()),
+            None => (SharedMatrix::new(rows, dim, init), SharedMatrix::new(osz, dim, (0..).map(|_| 0.0)), 0,
+                     vec![WorkerState::default(); nthreads]),
+        };
+
+        let words_carried_over = match self.pretrained {
+            Some(ref p) if self.resume.is_none() => seed_from(&input_matrix, &dict, args, &FastTextModel::load(p)?)?,
+            _ => 0,
+        };
+
+        let training = Training {
+            checkpoint: self.checkpoint.as_ref(),
+            workers: workers.into_iter().map(Mutex::new).collect(),
+            token_count: AtomicU64::new(token_count),
+            ..Training::new(args, &dict, input_matrix, output_matrix, input_size, self.seed)
+        };
+        let ntokens = dict.ntokens() as u64;
+        let total = args.epoch as u64 * ntokens;
+        let mut summary = TrainingSummary { words_carried_over, ..TrainingSummary::default() };
+        let validation = match self.validation {
+            Some(ref v) => v,
+            None => {
+                training.run_threads(input, total, self.resume.is_some())?;
+                let (input, output) = (training.input.into_matrix(), training.output.into_matrix());
+                let model = FastTextModel::from_parts(args.clone(), dict, input, output)?;
+                return Ok((stamp(model, fingerprint), summary));
+            }
+        };
+
+        let mut best: Option<(f64, FastTextModel)> = None;
+        let mut since_best = 0;
+        for epoch in 0..args.epoch as u32 {
+            training.run_threads(input, (epoch as u64 + 1) * ntokens, epoch > 0)?;
+            let model = FastTextModel::from_parts(args.clone(), dict.clone(), training.input.to_matrix(),
+                                                  training.output.to_matrix())?;
+            let metrics = evaluate_epoch(&model, validation, epoch + 1)?;
+            if best.as_ref().is_none_or(|b| metrics.f1 > b.0) {
+                best = Some((metrics.f1, model));
+                summary.best_epoch = Some(epoch + 1);
+                since_best = 0;
+            } else {
+                since_best += 1;
+            }
+            summary.epochs.push(metrics);
+            if self.patience.is_some_and(|p| since_best >= p) && epoch + 1 < args.epoch as u32 {
+                summary.stopped_early = true;
+                break;
+            }
+        }
+        let (_, model) = best.expect("epoch is positive");
+        Ok((stamp(model, fingerprint), summary))
+    }
+}
+
+/// FNV-1a hash of the training data and everything else that determines a reproducible run.
+fn fingerprint(input: &Path, args: &TrainArgs, seed: u64) -> io::Result<u64> {
+    let mut h: u64 = 0xcbf29ce484222325;
+    let mut update = |bytes: &[u8]| for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    };
+    let mut r = BufReader::new(File::open(input)?);
+    loop {
+        let n = {
+            let buf = r.fill_buf()?;
+            update(buf);
+            buf.len()
+        };
+        if n == 0 {
+            break;
+        }
+        r.consume(n);
+    }
+    let mut settings = Vec::new();
+    args.write(&mut settings)?;
+    write_f64(&mut settings, args.lr)?;
+    write_i32(&mut settings, args.min_count_label)?;
+    write_cstring(&mut settings, &args.label)?;
+    write_i64(&mut settings, seed as i64)?;
+    update(&settings);
+    Ok(h)
+}
+
+fn stamp(model: FastTextModel, fingerprint: Option<u64>) -> FastTextModel {
+    match fingerprint {
+        Some(f) => model.with_fingerprint(f),
+        None => model,
+    }
+}
+
+/// copies the input rows of the words (and, with the same hashing, n-gram buckets) that dict
+/// shares with prev into input; returns the number of words copied.
+fn seed_from(input: &SharedMatrix, dict: &Dictionary, args: &TrainArgs, prev: &FastTextModel) -> io::Result<usize> {
+    if prev.dim() != args.dim as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "pretrained model has dimension {}, not {}", prev.dim(), args.dim)));
+    }
+    let (prev_input, _) = prev.dense_matrices()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "quantized models cannot be used as pretrained vectors"))?;
+    let nwords = dict.nwords() as usize;
+    let mut carried = 0;
+    for (i, e) in dict.entries()[..nwords].iter().enumerate() {
+        if let Some(j) = prev.word_id(&e.word) {
+            input.set_row(i, &prev_input.row(j));
+            carried += 1;
+        }
+    }
+    let prev_nwords = prev.words().len();
+    let p = prev.args();
+    if p.bucket == args.bucket && p.minn == args.minn && p.maxn == args.maxn && prev_input.rows() == prev_nwords + args.bucket.max(0) as usize {
+        for b in 0..args.bucket.max(0) as usize {
+            input.set_row(nwords + b, &prev_input.row(prev_nwords + b));
+        }
+    }
+    Ok(carried)
+}
+
+/// the model of `FastTextModel::fine_tune`, without saving it.
+pub fn fine_tune(model: &FastTextModel, examples: &Path, epochs: u32, lr: f64, grow: bool) -> io::Result<FastTextModel> {
+    let args = model.args();
+    if args.model != ModelName::Supervised {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "only supervised models can be fine-tuned"));
+    }
+    if epochs == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "epochs must be positive"));
+    }
+    let (input, output) = model.dense_matrices()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "quantized models cannot be fine-tuned"))?;
+    let old = model.dictionary();
+    let (entries, ntokens) = count_entries(args, BufReader::new(File::open(examples)?))?;
+    let dict = if grow { old.extend(entries, ntokens) } else { old.clone() };
+    if dict.nlabels() != old.nlabels() && args.loss == LossName::HierarchicalSoftmax {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "labels cannot be added to a model trained with hierarchical softmax"));
+    }
+
+    // new word rows go between the old word rows and the buckets
+    let dim = args.dim as usize;
+    let (nwords, added) = (old.nwords() as usize, (dict.nwords() - old.nwords()) as usize);
+    let mut rng = MinstdRand::new(1);
+    let a = 1.0 / dim as f64;
+    let new_rows: Vec<f32> = (0..added * dim).map(|_| (rng.uniform() * 2.0 * a - a) as f32).collect();
+    let data = input.data();
+    let values = data[..nwords * dim].iter().cloned().chain(new_rows).chain(data[nwords * dim..].iter().cloned());
+    let input = SharedMatrix::new(input.rows() + added, dim, values);
+    let output = SharedMatrix::new(dict.nlabels() as usize, dim, output.data().iter().cloned().chain(std::iter::repeat(0.0)));
+
+    let targs = TrainArgs { epoch: epochs as i32, lr, ..args.clone() };
+    let training = Training {
+        epoch_tokens: ntokens as u64,
+        ..Training::new(&targs, &dict, input, output, fs::metadata(examples)?.len(), None)
+    };
+    training.run_threads(examples, epochs as u64 * ntokens as u64, false)?;
+    let (input, output) = (training.input.into_matrix(), training.output.into_matrix());
+    FastTextModel::from_parts(args.clone(), dict, input, output)
+}
+
+/// scores a model on a labeled file: P@1 and R@1 as `FastTextModel::test` computes them, and
+/// the average of -log p(label) over the labels of every example.
+fn evaluate_epoch(model: &FastTextModel, path: &Path, epoch: u32) -> io::Result<EpochMetrics> {
+    let e = model.test(path, 1)?;
+    let known = model.labels();
+    let (mut loss, mut n) = (0.0, 0u64);
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let labels: Vec<&str> = tokenize(&line).into_iter().filter(|t| known.contains(t)).collect();
+        if labels.is_empty() {
+            continue;
+        }
+        let probs = model.predict(&line, known.len());
+        let p = |l: &str| probs.iter().find(|p| p.0 == l).map_or(0.0, |p| p.1);
+        loss -= labels.iter().map(|l| std_log(p(l)) as f64).sum::<f64>() / labels.len() as f64;
+        n += 1;
+    }
+    let f1 = if e.precision + e.recall > 0.0 { 2.0 * e.precision * e.recall / (e.precision + e.recall) } else { 0.0 };
+    Ok(EpochMetrics { epoch, loss: if n > 0 { loss / n as f64 } else { 0.0 }, precision: e.precision, recall: e.recall, f1 })
+}
+
+/// class ids repeated in proportion to the square roots of their counts, shuffled, as
+/// fastText's Model::initTableNegatives builds them.
+fn negative_table(counts: &[i64], seed: u64) -> Vec<u32> {
+    let z: f64 = counts.iter().map(|&c| (c as f64).sqrt()).sum();
+    let mut table = Vec::with_capacity(NEGATIVE_TABLE_SIZE);
+    for (i, &c) in counts.iter().enumerate() {
+        let n = ((c as f64).sqrt() * NEGATIVE_TABLE_SIZE as f64 / z) as usize;
+        table.extend(std::iter::repeat_n(i as u32, n.max(1)));
+    }
+    MinstdRand::new(seed).shuffle(&mut table);
+    table
+}
+
+/// probability of keeping each word when subsampling frequent words with threshold t, as in
+/// fastText's Dictionary::initTableDiscard.
+fn keep_probs(counts: &[i64], ntokens: i64, t: f64) -> Vec<f32> {
+    counts.iter().map(|&c| {
+        let f = c as f64 / ntokens as f64;
+        ((t / f).sqrt() + t / f) as f32
+    }).collect()
+}
+
+/// reads the training state of a checkpoint, with the arguments of the run.
+fn read_checkpoint<R: Read>(r: &mut R) -> io::Result<(TrainArgs, Checkpoint)> {
+    if read_i32(r)? != FASTTEXT_FILEFORMAT_MAGIC_INT32 || read_i32(r)? != FASTTEXT_VERSION {
+        return Err(invalid("not a training checkpoint"));
+    }
+    let mut args = TrainArgs::read(r)?;
+    let dict = Dictionary::read(r, &args)?;
+    read_bool(r)?;
+    let input = Matrix::read(r)?;
+    read_bool(r)?;
+    let output = Matrix::read(r)?;
+    if read_i32(r)? != CHECKPOINT_MAGIC {
+        return Err(invalid("model file without training state"));
+    }
+    args.lr = read_f64(r)?;
+    args.label = read_cstring(r)?;
+    let seeded = read_bool(r)?;
+    let seed = read_i64(r)? as u64;
+    let seed = if seeded { Some(seed) } else { None };
+    let input_size = read_i64(r)? as u64;
+    let token_count = read_i64(r)? as u64;
+    let nworkers = read_i32(r)?;
+    if nworkers <= 0 {
+        return Err(invalid("checkpoint without training threads"));
+    }
+    args.thread = nworkers;
+    let mut workers = Vec::new();
+    for _ in 0..nworkers {
+        workers.push(WorkerState {
+            pos: read_i64(r)? as u64,
+            rng: read_i64(r)? as u64,
+            negpos: read_i64(r)? as u64,
+            local: read_i64(r)? as u64,
+        });
+    }
+    // the label prefix was read after the dictionary
+    let dict = Dictionary::new(&args, dict.entries().to_vec(), dict.ntokens());
+    Ok((args, Checkpoint { dict, input, output, input_size, token_count, seed, workers }))
+}
+
+impl<'a> Training<'a> {
+    /// training from the given matrices with a single thread state per `args.thread`, and
+    /// without checkpoints.
+    fn new(args: &'a TrainArgs, dict: &'a Dictionary, input: SharedMatrix, output: SharedMatrix, input_size: u64,
+           seed: Option<u64>) -> Training<'a> {
+        let supervised = args.model == ModelName::Supervised;
+        let counts = dict.counts(if supervised { EntryType::Label } else { EntryType::Word });
+        Training {
+            args,
+            dict,
+            checkpoint: None,
+            input_size,
+            epoch_tokens: dict.ntokens() as u64,
+            seed,
+            workers: (0..args.thread).map(|_| Mutex::new(WorkerState::default())).collect(),
+            input,
+            output,
+            osz: counts.len(),
+            tree: if args.loss == LossName::HierarchicalSoftmax { Some(HuffmanTree::new(&counts)) } else { None },
+            negatives: if args.loss == LossName::NegativeSampling { negative_table(&counts, seed.unwrap_or(0)) } else { Vec::new() },
+            pkeep: if supervised { Vec::new() } else { keep_probs(&counts, dict.ntokens(), args.t) },
+            token_count: AtomicU64::new(0),
+        }
+    }
+
+    /// trains with every thread until `until` tokens have been processed, starting from the
+    /// threads' saved states if `from_state`.
+    fn run_threads(&self, path: &Path, until: u64, from_state: bool) -> io::Result<()> {
+        let nthreads = self.workers.len();
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..nthreads)
+                .map(|id| s.spawn(move || self.run(id, nthreads, path, until, from_state)))
+                .collect();
+            handles.into_iter()
+                .map(|h| h.join().unwrap_or_else(|_| Err(io::Error::other("training thread panicked"))))
+                .collect::<io::Result<Vec<()>>>()
+        })?;
+        Ok(())
+    }
+
+    /// trains on the part of the corpus that starts at the id-th of nthreads equal slices of
+    /// the file, wrapping around, until all threads together have processed `until` tokens.
+    fn run(&self, id: usize, nthreads: usize, path: &Path, until: u64, from_state: bool) -> io::Result<()> {
+        let mut r = BufReader::new(File::open(path)?);
+        let mut line = Vec::new();
+        let (mut w, mut pos, mut local) = if from_state {
+            let state = *self.workers[id].lock().unwrap();
+            let mut w = Worker::new(self, state.rng);
+            w.negpos = state.negpos as usize;
+            (w, state.pos, state.local)
+        } else {
+            let mut pos = self.input_size * id as u64 / nthreads as u64;
+            r.seek(SeekFrom::Start(pos))?;
+            if pos > 0 {
+                // finish the line the slice starts in
+                pos += r.read_until(b'\n', &mut line)? as u64;
+            }
+            let mut w = Worker::new(self, self.seed.unwrap_or(0) + id as u64);
+            if !self.negatives.is_empty() {
+                w.negpos = w.rng.below(self.negatives.len());
+            }
+            (w, pos, 0)
+        };
+        r.seek(SeekFrom::Start(pos))?;
+
+        let total = self.args.epoch as u64 * self.epoch_tokens;
+        while self.token_count.load(Ordering::Relaxed) < until {
+            line.clear();
+            let n = r.read_until(b'\n', &mut line)?;
+            if n == 0 {
+                r.seek(SeekFrom::Start(0))?;
+                pos = 0;
+                continue;
+            }
+            pos += n as u64;
+            let progress = self.token_count.load(Ordering::Relaxed) as f64 / total as f64;
+            let lr = (self.args.lr * (1.0 - progress)) as f32;
+            let text = String::from_utf8_lossy(&line);
+            local += tokenize(&text).len() as u64;
+            if self.args.model == ModelName::Supervised {
+                let (words, labels) = self.dict.get_line(&text);
+                if !words.is_empty() && !labels.is_empty() {
+                    w.supervised(&words, &labels, lr);
+                }
+            } else {
+                let words = w.subsample(self.dict.get_word_ids(&text));
+                for sentence in words.chunks(MAX_LINE_SIZE) {
+                    match self.args.model {
+                        ModelName::Cbow => w.cbow(sentence, lr),
+                        _ => w.skipgram(sentence, lr),
+                    }
+                }
+            }
+            let mut flushed = None;
+            if local > self.args.lr_update_rate as u64 {
+                let before = self.token_count.fetch_add(local, Ordering::Relaxed);
+                flushed = Some((before, before + local));
+                local = 0;
+            }
+            *self.workers[id].lock().unwrap() = WorkerState {
+                pos, rng: w.rng.state(), negpos: w.negpos as u64, local,
+            };
+            if let (Some(&(ref path, interval)), Some((before, after))) = (self.checkpoint, flushed) {
+                if before / interval != after / interval && after < total {
+                    self.save_checkpoint(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// writes the model and the state of every thread to a temporary file, then moves it to
+    /// path. Other threads keep training meanwhile, so only single-threaded checkpoints are
+    /// exact snapshots.
+    fn save_checkpoint(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = OsString::from(path.as_os_str());
+        tmp.push(".tmp");
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        write_i32(&mut w, FASTTEXT_FILEFORMAT_MAGIC_INT32)?;
+        write_i32(&mut w, FASTTEXT_VERSION)?;
+        self.args.write(&mut w)?;
+        self.dict.write(&mut w)?;
+        write_bool(&mut w, false)?;
+        self.input.write(&mut w)?;
+        write_bool(&mut w, false)?;
+        self.output.write(&mut w)?;
+
+        write_i32(&mut w, CHECKPOINT_MAGIC)?;
+        write_f64(&mut w, self.args.lr)?;
+        write_cstring(&mut w, &self.args.label)?;
+        write_bool(&mut w, self.seed.is_some())?;
+        write_i64(&mut w, self.seed.unwrap_or(0) as i64)?;
+        write_i64(&mut w, self.input_size as i64)?;
+        write_i64(&mut w, self.token_count.load(Ordering::Relaxed) as i64)?;
+        write_i32(&mut w, self.workers.len() as i32)?;
+        for state in &self.workers {
+            let state = *state.lock().unwrap();
+            for &v in &[state.pos, state.rng, state.negpos, state.local] {
+                write_i64(&mut w, v as i64)?;
+            }
+        }
+        w.into_inner()?.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+impl<'a> Worker<'a> {
+    fn new(t: &'a Training<'a>, seed: u64) -> Worker<'a> {
+        let dim = t.args.dim as usize;
+        Worker { t, hidden: vec![0.0; dim], grad: vec![0.0; dim], scores: Vec::new(), rng: MinstdRand::new(seed), negpos: 0 }
+    }
+
+    /// drops frequent words at random, keeping each with its probability in pkeep.
+    fn subsample(&mut self, mut words: Vec<i32>) -> Vec<i32> {
+        let pkeep = &self.t.pkeep;
+        let rng = &mut self.rng;
+        words.retain(|&w| rng.uniform() as f32 <= pkeep[w as usize]);
+        words
+    }
+
+    /// one SGD step on a supervised example: one of its labels, picked at random, or all of
+    /// them with one-vs-all loss.
+    fn supervised(&mut self, words: &[i32], labels: &[i32], lr: f32) {
+        self.compute_hidden(words);
+        if self.t.args.loss == LossName::OneVsAll {
+            self.one_vs_all(labels, lr);
+        } else {
+            let target = labels[self.rng.below(labels.len())] as usize;
+            self.loss(target, lr);
+        }
+        self.update_input(words, 1.0 / words.len() as f32);
+    }
+
+    /// predicts every word of the sentence from its subwords, for each context word within a
+    /// window whose size is drawn from 1 to ws.
+    fn skipgram(&mut self, sentence: &[i32], lr: f32) {
+        let dict = self.t.dict;
+        for w in 0..sentence.len() {
+            let boundary = 1 + self.rng.below(self.t.args.ws as usize);
+            let ngrams = &dict.entries()[sentence[w] as usize].subwords;
+            let window = sentence.iter().enumerate().take(w + boundary + 1).skip(w.saturating_sub(boundary));
+            for (c, &context) in window {
+                if c != w {
+                    self.compute_hidden(ngrams);
+                    self.loss(context as usize, lr);
+                    self.update_input(ngrams, 1.0);
+                }
+            }
+        }
+    }
+
+    /// predicts every word of the sentence from the subwords of the words around it, within a
+    /// window whose size is drawn from 1 to ws.
+    fn cbow(&mut self, sentence: &[i32], lr: f32) {
+        let dict = self.t.dict;
+        let mut bow = Vec::new();
+        for w in 0..sentence.len() {
+            let boundary = 1 + self.rng.below(self.t.args.ws as usize);
+            bow.clear();
+            let window = sentence.iter().enumerate().take(w + boundary + 1).skip(w.saturating_sub(boundary));
+            for (c, &context) in window {
+                if c != w {
+                    bow.extend_from_slice(&dict.entries()[context as usize].subwords);
+                }
+            }
+            if !bow.is_empty() {
+                self.compute_hidden(&bow);
+                self.loss(sentence[w] as usize, lr);
+                self.update_input(&bow, 1.0);
+            }
+        }
+    }
+
+    /// sets hidden to the average of the input rows and clears the gradient.
+    fn compute_hidden(&mut self, input: &[i32]) {
+        for h in self.hidden.iter_mut() {
+            *h = 0.0;
+        }
+        for &i in input {
+            self.t.input.add_row_to(&mut self.hidden, i as usize, 1.0 / input.len() as f32);
+        }
+        for g in self.grad.iter_mut() {
+            *g = 0.0;
+        }
+    }
+
+    /// adds `a` times the gradient to every input row.
+    fn update_input(&mut self, input: &[i32], a: f32) {
+        for &i in input {
+            self.t.input.add_to_row(i as usize, &self.grad, a);
+        }
+    }
+
+    /// updates the output matrix towards target with the configured loss. One-vs-all, which
+    /// `train_with_summary` only accepts for supervised models, goes through `one_vs_all`.
+    fn loss(&mut self, target: usize, lr: f32) -> f32 {
+        match self.t.args.loss {
+            LossName::NegativeSampling => self.negative_sampling(target, lr),
+            LossName::HierarchicalSoftmax => self.hierarchical_softmax(target, lr),
+            _ => self.softmax(target, lr),
+        }
+    }
+
+    /// updates output row `row` towards `label` and accumulates the input gradient; returns the
+    /// loss.
+    fn binary_logistic(&mut self, row: usize, label: bool, lr: f32) -> f32 {
+        let score = sigmoid(self.t.output.dot_row(&self.hidden, row));
+        let alpha = lr * (label as u8 as f32 - score);
+        self.t.output.add_row_to(&mut self.grad, row, alpha);
+        self.t.output.add_to_row(row, &self.hidden, alpha);
+        if label { -std_log(score) } else { -std_log(1.0 - score) }
+    }
+
+    fn negative_sampling(&mut self, target: usize, lr: f32) -> f32 {
+        let mut loss = self.binary_logistic(target, true, lr);
+        for _ in 0..self.t.args.neg {
+            let negative = self.negative(target);
+            loss += self.binary_logistic(negative, false, lr);
+        }
+        loss
+    }
+
+    /// the next class of the negative table other than target.
+    fn negative(&mut self, target: usize) -> usize {
+        let table = &self.t.negatives;
+        loop {
+            let n = table[self.negpos] as usize;
+            self.negpos = (self.negpos + 1) % table.len();
+            if n != target || self.t.osz == 1 {
+                return n;
+            }
+        }
+    }
+
+    fn hierarchical_softmax(&mut self, target: usize, lr: f32) -> f32 {
+        let t = self.t;
+        let path = t.tree.as_ref().unwrap().path(target);
+        path.iter().map(|&(row, code)| self.binary_logistic(row, code, lr)).sum()
+    }
+
+    fn softmax(&mut self, target: usize, lr: f32) -> f32 {
+        let osz = self.t.osz;
+        self.scores.clear();
+        for i in 0..osz {
+            let s = self.t.output.dot_row(&self.hidden, i);
+            self.scores.push(s);
+        }
+        let max = self.scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let mut z = 0.0;
+        for s in self.scores.iter_mut() {
+            *s = (*s - max).exp();
+            z += *s;
+        }
+        for i in 0..osz {
+            let p = self.scores[i] / z;
+            let alpha = lr * ((i == target) as u8 as f32 - p);
+            self.t.output.add_row_to(&mut self.grad, i, alpha);
+            self.t.output.add_to_row(i, &self.hidden, alpha);
+        }
+        -std_log(self.scores[target] / z)
+    }
+
+    fn one_vs_all(&mut self, labels: &[i32], lr: f32) -> f32 {
+        (0..self.t.osz)
+            .map(|i| self.binary_logistic(i, labels.contains(&(i as i32)), lr))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    /// a corpus where the label is given away by one word of each line.
+    fn corpus(name: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("fast_text_{}_{}.txt", process::id(), name));
+        let mut rng = MinstdRand::new(9);
+        let filler = ["the", "a", "of", "and", "to", "in", "is", "it"];
+        let topics = [("cheese", "__label__food"), ("sauce", "__label__food"), ("goal", "__label__sport"),
+                      ("match", "__label__sport"), ("vote", "__label__politics"), ("senate", "__label__politics")];
+        let mut text = String::new();
+        for _ in 0..600 {
+            let (word, label) = topics[rng.below(topics.len())];
+            text += label;
+            for i in 0..6 {
+                text.push(' ');
+                text += if i == 3 { word } else { filler[rng.below(filler.len())] };
+            }
+            text.push('\n');
+        }
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_train_supervised() {
+        let path = corpus("supervised");
+        for &loss in &[LossName::Softmax, LossName::NegativeSampling, LossName::HierarchicalSoftmax, LossName::OneVsAll] {
+            let args = TrainArgs { dim: 10, epoch: 5, loss, thread: 1, ..TrainArgs::supervised() };
+            let model = Trainer::new(args).train(&path).unwrap();
+            assert_eq!(model.labels().len(), 3);
+            assert_eq!(model.predict("the cheese of it", 1)[0].0, "__label__food", "{:?}", loss);
+            assert_eq!(model.predict("and a senate vote", 1)[0].0, "__label__politics", "{:?}", loss);
+            assert_eq!(model.predict("in the match", 1)[0].0, "__label__sport", "{:?}", loss);
+        }
+
+        let args = TrainArgs { dim: 10, thread: 4, word_ngrams: 2, bucket: 1000, ..TrainArgs::supervised() };
+        let model = Trainer::new(args).train(&path).unwrap();
+        let saved = path.with_extension("bin");
+        model.save(&saved).unwrap();
+        let loaded = FastTextModel::load(&saved).unwrap();
+        assert_eq!(loaded.predict("goal", 1)[0].0, "__label__sport");
+        fs::remove_file(&saved).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sample_text() {
+        let args = TrainArgs { epoch: 50, lr: 0.5, thread: 1, ..TrainArgs::supervised() };
+        let model = Trainer::new(args).train("sample_text.txt").unwrap();
+        let e = model.test("sample_text.txt", 1).unwrap();
+        assert_eq!(e.n, 40);
+        assert!(e.precision > 0.9, "{:?}", e);
+    }
+
+    /// text where words only occur next to words of the same topic.
+    fn topics_corpus(name: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("fast_text_{}_{}.txt", process::id(), name));
+        let mut rng = MinstdRand::new(3);
+        let topics = [["apple", "pear", "plum", "grape", "melon"],
+                      ["train", "bus", "tram", "ferry", "taxi"],
+                      ["violin", "cello", "flute", "harp", "drum"]];
+        let mut text = String::new();
+        for _ in 0..300 {
+            let topic = &topics[rng.below(topics.len())];
+            let words: Vec<&str> = (0..8).map(|_| topic[rng.below(topic.len())]).collect();
+            text += &words.join(" ");
+            text.push('\n');
+        }
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_train_unsupervised() {
+        let path = topics_corpus("unsupervised");
+        let cos = |a: &[f32], b: &[f32]| {
+            let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+            dot / (a.iter().map(|x| x * x).sum::<f32>() * b.iter().map(|x| x * x).sum::<f32>()).sqrt()
+        };
+        for &model in &[ModelName::Skipgram, ModelName::Cbow] {
+            for &loss in &[LossName::NegativeSampling, LossName::HierarchicalSoftmax, LossName::Softmax] {
+                let args = TrainArgs { model, loss, dim: 16, epoch: 10, lr: 0.1, min_count: 1, bucket: 1000,
+                                       t: 1.0, thread: 2, ..TrainArgs::default() };
+                let m = Trainer::new(args).train(&path).unwrap();
+                assert_eq!(m.words().len(), 16);
+                let (apple, pear, bus) = (m.word_vector("apple"), m.word_vector("pear"), m.word_vector("bus"));
+                assert!(cos(&apple, &pear) > cos(&apple, &bus), "{:?} {:?}", model, loss);
+                assert!(cos(&m.word_vector("harp"), &m.word_vector("drum")) > cos(&m.word_vector("harp"), &pear),
+                        "{:?} {:?}", model, loss);
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_validation() {
+        let path = corpus("validation_train");
+        let valid = corpus("validation_valid");
+        let args = TrainArgs { dim: 10, epoch: 6, lr: 0.05, thread: 1, ..TrainArgs::supervised() };
+        let (model, summary) = Trainer::new(args.clone()).validation(&valid).train_with_summary(&path).unwrap();
+        assert_eq!(summary.epochs.iter().map(|e| e.epoch).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6]);
+        assert!(!summary.stopped_early);
+        assert!(summary.epochs[5].loss < summary.epochs[0].loss, "{:?}", summary);
+        let best = summary.best_epoch.unwrap();
+        let e = &summary.epochs[best as usize - 1];
+        assert!(summary.epochs.iter().all(|m| m.f1 <= e.f1 && m.f1 >= 0.0 && m.f1 <= 1.0));
+        assert!((model.test(&valid, 1).unwrap().precision - e.precision).abs() < 1e-9);
+
+        // the data is easy enough for F1 to reach 1 and stop improving
+        let args = TrainArgs { epoch: 50, ..args };
+        let (_, summary) = Trainer::new(args).validation(&valid).early_stopping(2).train_with_summary(&path).unwrap();
+        assert!(summary.stopped_early);
+        assert_eq!(summary.epochs.len() as u32, summary.best_epoch.unwrap() + 2);
+
+        let (_, summary) = Trainer::new(TrainArgs::supervised()).train_with_summary(&path).unwrap();
+        assert_eq!(summary, TrainingSummary::default());
+        let args = TrainArgs { model: ModelName::Cbow, ..TrainArgs::default() };
+        assert!(Trainer::new(args).validation(&valid).train(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&valid).unwrap();
+    }
+
+    #[test]
+    fn test_pretrained() {
+        let path = topics_corpus("pretrained");
+        let prev_path = path.with_extension("bin");
+        let args = TrainArgs { dim: 8, epoch: 2, min_count: 1, bucket: 500, thread: 1, t: 1.0, ..TrainArgs::default() };
+        let prev = Trainer::new(args.clone()).train(&path).unwrap();
+        prev.save(&prev_path).unwrap();
+
+        let next = path.with_extension("next");
+        fs::write(&next, fs::read_to_string(&path).unwrap() + "kiwi apple kiwi\n").unwrap();
+        // without updates, shared words keep their vectors exactly
+        let (model, summary) = Trainer::new(TrainArgs { lr: 0.0, ..args.clone() }).pretrained(&prev_path)
+            .train_with_summary(&next).unwrap();
+        assert_eq!(summary.words_carried_over, 16);
+        assert_eq!(model.words().len(), 17);
+        assert_eq!(model.word_vector("apple"), prev.word_vector("apple"));
+        assert_eq!(model.word_vector("</s>"), prev.word_vector("</s>"));
+
+        let (_, summary) = Trainer::new(args.clone()).train_with_summary(&next).unwrap();
+        assert_eq!(summary.words_carried_over, 0);
+        assert!(Trainer::new(TrainArgs { dim: 4, ..args }).pretrained(&prev_path).train(&next).is_err());
+        for p in &[&path, &prev_path, &next] {
+            fs::remove_file(p).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fine_tune() {
+        let path = corpus("fine_tune");
+        let args = TrainArgs { dim: 10, thread: 1, ..TrainArgs::supervised() };
+        let model = Trainer::new(args.clone()).train(&path).unwrap();
+        let examples = path.with_extension("new");
+        let mut text = String::new();
+        for i in 0..50 {
+            text += if i % 2 == 0 { "__label__music the guitar of it\n" } else { "__label__sport a goal\n" };
+        }
+        fs::write(&examples, text).unwrap();
+        let out = path.with_extension("bin");
+
+        let same = model.fine_tune(&examples, 5, 0.1, false, &out).unwrap();
+        assert_eq!(same.labels(), model.labels());
+        assert!(!same.contains("guitar"));
+
+        let tuned = model.fine_tune(&examples, 5, 0.1, true, &out).unwrap();
+        assert_eq!(tuned.labels().len(), 4);
+        assert_eq!(&tuned.words()[..model.words().len()], &model.words()[..]);
+        assert_eq!(tuned.predict("guitar", 1)[0].0, "__label__music");
+        assert_eq!(tuned.predict("the cheese of it", 1)[0].0, "__label__food");
+        assert_eq!(FastTextModel::load(&out).unwrap().labels().len(), 4);
+
+        let hs = Trainer::new(TrainArgs { loss: LossName::HierarchicalSoftmax, ..args }).train(&path).unwrap();
+        assert!(hs.fine_tune(&examples, 1, 0.1, true, &out).is_err());
+        assert!(hs.fine_tune(&examples, 1, 0.1, false, &out).is_ok());
+        for p in &[&path, &examples, &out] {
+            fs::remove_file(p).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let path = topics_corpus("checkpoint");
+        let ckpt = path.with_extension("ckpt");
+        let args = TrainArgs { dim: 8, epoch: 4, min_count: 1, bucket: 500, thread: 1, ..TrainArgs::default() };
+        let mut expected = Vec::new();
+        Trainer::new(args.clone()).train(&path).unwrap().write(&mut expected).unwrap();
+
+        // 4 epochs of 2700 tokens: a single checkpoint, half way through
+        let trainer = Trainer::new(args).checkpoint(&ckpt, 6000);
+        let mut uninterrupted = Vec::new();
+        trainer.train(&path).unwrap().write(&mut uninterrupted).unwrap();
+        assert_eq!(uninterrupted, expected);
+
+        let mut resumed = Vec::new();
+        Trainer::resume_from(&ckpt).unwrap().train(&path).unwrap().write(&mut resumed).unwrap();
+        assert_eq!(resumed, expected);
+        // a checkpoint is also a model
+        assert_eq!(FastTextModel::load(&ckpt).unwrap().words().len(), 16);
+
+        let other = topics_corpus("checkpoint_other");
+        fs::write(&other, "apple pear\n").unwrap();
+        assert!(Trainer::resume_from(&ckpt).unwrap().train(&other).is_err());
+        let err = Trainer::resume_from(&ckpt).unwrap().validation(&path).train(&path).unwrap_err();
+        assert_eq!(err.to_string(), "a resumed run cannot be validated");
+        assert!(Trainer::resume_from(&other).is_err());
+        let mut trainer = Trainer::resume_from(&ckpt).unwrap();
+        trainer.args.bucket += 1;
+        assert_eq!(trainer.train(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        for p in &[&path, &ckpt, &other] {
+            fs::remove_file(p).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reproducible() {
+        let path = corpus("reproducible");
+        let saved = path.with_extension("bin");
+        let args = TrainArgs { dim: 10, epoch: 3, loss: LossName::NegativeSampling, ..TrainArgs::supervised() };
+        let trainer = Trainer::new(args.clone()).reproducible(7);
+        let model = trainer.train(&path).unwrap();
+        model.save(&saved).unwrap();
+        assert!(trainer.verify_reproducible(&path, &saved).unwrap());
+        let loaded = FastTextModel::load(&saved).unwrap();
+        assert!(loaded.fingerprint().is_some());
+        assert_eq!(loaded.fingerprint(), model.fingerprint());
+
+        assert!(!Trainer::new(args.clone()).reproducible(8).verify_reproducible(&path, &saved).unwrap());
+        assert!(Trainer::new(args.clone()).verify_reproducible(&path, &saved).is_err());
+        let other = Trainer::new(TrainArgs { lr: 0.2, ..args.clone() }).reproducible(7).train(&path).unwrap();
+        assert!(other.fingerprint() != model.fingerprint());
+        assert_eq!(Trainer::new(args).train(&path).unwrap().fingerprint(), None);
+        fs::remove_file(&saved).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keep_probs() {
+        let p = keep_probs(&[100, 1], 101, 1e-2);
+        assert!((p[0] - ((0.0101f64).sqrt() + 0.0101) as f32).abs() < 1e-6);
+        assert!(p[1] > 1.0);
+    }
+
+    #[test]
+    fn test_rejects_bad_args() {
+        let args = TrainArgs { dim: 0, ..TrainArgs::supervised() };
+        assert!(Trainer::new(args).train("sample_text.txt").is_err());
+        let args = TrainArgs { min_count: 1000, ..TrainArgs::supervised() };
+        assert!(Trainer::new(args).train("sample_text.txt").is_err());
+        let args = TrainArgs { loss: LossName::OneVsAll, ..TrainArgs::default() };
+        let e = Trainer::new(args).train("sample_text.txt").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,272 @@
+//! Reading and writing the `.vec` text format: a `<words> <dim>` header line, then one
+//! `word f1 f2 ...` line per word. fastText writes it next to every unsupervised model and
+//! reads it for `-pretrainedVectors`.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Streams the entries of a `.vec` file.
+///
+/// ```no_run
+/// use fast_text::vec_file::VecReader;
+///
+/// // the 1000 most frequent words of a model
+/// for entry in VecReader::open("sample.vec").unwrap().limit(1000) {
+///     let (word, vector) = entry.unwrap();
+///     println!("{} {:?}", word, vector);
+/// }
+/// ```
+pub struct VecReader<R: BufRead> {
+    reader: R,
+    len: usize,
+    dim: usize,
+    line: usize,
+    returned: usize,
+    limit: Option<usize>,
+    vocabulary: Option<HashSet<String>>,
+}
+
+impl VecReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<VecReader<BufReader<File>>> {
+        VecReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+fn invalid(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {} of .vec file: {}", line, msg))
+}
+
+impl<R: BufRead> VecReader<R> {
+    /// reads the header; entries are read as the reader is iterated.
+    pub fn new(mut reader: R) -> io::Result<VecReader<R>> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let fields: Vec<usize> = header.split_whitespace().map(|f| f.parse::<usize>()).collect::<Result<_, _>>()
+            .map_err(|_| invalid(1, "header should be the number of words and the dimension"))?;
+        if fields.len() != 2 {
+            return Err(invalid(1, "header should be the number of words and the dimension"));
+        }
+        Ok(VecReader { reader, len: fields[0], dim: fields[1], line: 1, returned: 0, limit: None, vocabulary: None })
+    }
+
+    /// Number of words according to the header.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Dimension of the vectors according to the header.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Stops after n entries (the n most frequent words, for files written by fastText). With a
+    /// vocabulary, these are the first n entries of words in it.
+    pub fn limit(mut self, n: usize) -> VecReader<R> {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Skips the entries of words that are not in the given vocabulary.
+    pub fn vocabulary(mut self, words: HashSet<String>) -> VecReader<R> {
+        self.vocabulary = Some(words);
+        self
+    }
+
+    fn parse(&self, l: &str) -> io::Result<(String, Vec<f32>)> {
+        let mut it = l.split(' ').filter(|t| !t.is_empty());
+        let word = match it.next() {
+            Some(w) => w.to_string(),
+            None => return Err(invalid(self.line, "empty line")),
+        };
+        let v: Vec<f32> = it.map(|t| t.parse::<f32>()).collect::<Result<_, _>>()
+            .map_err(|_| invalid(self.line, "malformed number"))?;
+        if v.len() != self.dim {
+            return Err(invalid(self.line, &format!("expected {} values but found {}", self.dim, v.len())));
+        }
+        Ok((word, v))
+    }
+}
+
+impl<R: BufRead> Iterator for VecReader<R> {
+    type Item = io::Result<(String, Vec<f32>)>;
+
+    fn next(&mut self) -> Option<io::Result<(String, Vec<f32>)>> {
+        let mut l = String::new();
+        loop {
+            if self.limit.is_some_and(|n| self.returned >= n) {
+                return None;
+            }
+            l.clear();
+            match self.reader.read_line(&mut l) {
+                Ok(0) => return None,
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            self.line += 1;
+            let l = l.trim_end_matches(['\n', '\r']);
+            if l.is_empty() {
+                continue;
+            }
+            if let Some(ref vocab) = self.vocabulary {
+                if !l.split(' ').next().is_some_and(|w| vocab.contains(w)) {
+                    continue;
+                }
+            }
+            self.returned += 1;
+            return Some(self.parse(l));
+        }
+    }
+}
+
+/// Reads all (or, with a limit, the first `limit`) entries of a `.vec` file.
+pub fn read_vec<P: AsRef<Path>>(path: P, limit: Option<usize>) -> io::Result<Vec<(String, Vec<f32>)>> {
+    let r = VecReader::open(path)?;
+    let r = match limit {
+        Some(n) => r.limit(n),
+        None => r,
+    };
+    r.collect()
+}
+
+/// Writes `.vec` files. The number of entries must be known up front for the header.
+pub struct VecWriter<W: Write> {
+    writer: W,
+    dim: usize,
+    precision: usize,
+}
+
+impl VecWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, len: usize, dim: usize) -> io::Result<VecWriter<BufWriter<File>>> {
+        VecWriter::new(BufWriter::new(File::create(path)?), len, dim)
+    }
+}
+
+impl<W: Write> VecWriter<W> {
+    /// writes the header for len entries of dimension dim.
+    pub fn new(mut writer: W, len: usize, dim: usize) -> io::Result<VecWriter<W>> {
+        writeln!(writer, "{} {}", len, dim)?;
+        Ok(VecWriter { writer, dim, precision: 5 })
+    }
+
+    /// Significant digits written per value. Defaults to 5, as fastText does.
+    pub fn precision(mut self, digits: usize) -> VecWriter<W> {
+        self.precision = digits.max(1);
+        self
+    }
+
+    pub fn write(&mut self, word: &str, v: &[f32]) -> io::Result<()> {
+        if v.len() != self.dim {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "vector for {} has {} values but the file has dimension {}", word, v.len(), self.dim)));
+        }
+        if word.is_empty() || word.contains(char::is_whitespace) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("cannot write word {:?}", word)));
+        }
+        write!(self.writer, "{} ", word)?;
+        for x in v {
+            write!(self.writer, "{} ", format_g(*x, self.precision))?;
+        }
+        writeln!(self.writer)
+    }
+
+    /// flushes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes entries to a new `.vec` file.
+pub fn write_vec<P: AsRef<Path>>(path: P, entries: &[(String, Vec<f32>)], precision: usize) -> io::Result<()> {
+    let dim = entries.first().map_or(0, |e| e.1.len());
+    let mut w = VecWriter::create(path, entries.len(), dim)?.precision(precision);
+    for (word, v) in entries {
+        w.write(word, v)?;
+    }
+    w.finish().map(|_| ())
+}
+
+/// Formats like C++ streams (and printf's %g) do with the given number of significant digits.
+pub fn format_g(x: f32, precision: usize) -> String {
+    if x == 0.0 || !x.is_finite() {
+        return if x == 0.0 { "0".to_string() } else { x.to_string() };
+    }
+    let sci = format!("{:.*e}", precision - 1, x);
+    let (mantissa, exp) = sci.split_at(sci.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    if exp < -4 || exp >= precision as i32 {
+        let mantissa = trim_zeros(mantissa);
+        format!("{}e{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.abs())
+    } else {
+        let decimals = (precision as i32 - 1 - exp).max(0) as usize;
+        trim_zeros(&format!("{:.*}", decimals, x)).to_string()
+    }
+}
+
+fn trim_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_g() {
+        assert_eq!(format_g(0.1234567, 5), "0.12346");
+        assert_eq!(format_g(-0.5, 5), "-0.5");
+        assert_eq!(format_g(1e-5, 5), "1e-05");
+        assert_eq!(format_g(0.0001, 5), "0.0001");
+        assert_eq!(format_g(123456.0, 5), "1.2346e+05");
+        assert_eq!(format_g(12345.0, 5), "12345");
+        assert_eq!(format_g(99999.9, 5), "1e+05");
+        assert_eq!(format_g(3.0, 3), "3");
+        assert_eq!(format_g(0.0, 5), "0");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut w = VecWriter::new(Vec::new(), 3, 2).unwrap().precision(3);
+        w.write("</s>", &[0.1, -0.25]).unwrap();
+        w.write("cheese", &[1.0, 0.33333]).unwrap();
+        w.write("sauce", &[2e-7, 100.0]).unwrap();
+        assert!(w.write("bad", &[1.0]).is_err());
+        assert!(w.write("two words", &[1.0, 2.0]).is_err());
+        let b = w.finish().unwrap();
+        assert_eq!(String::from_utf8(b.clone()).unwrap(),
+                   "3 2\n</s> 0.1 -0.25 \ncheese 1 0.333 \nsauce 2e-07 100 \n");
+
+        let r = VecReader::new(&b[..]).unwrap();
+        assert_eq!((r.len(), r.dim()), (3, 2));
+        let entries: Vec<_> = r.collect::<io::Result<_>>().unwrap();
+        assert_eq!(entries[1], ("cheese".to_string(), vec![1.0, 0.333]));
+
+        let limited: Vec<_> = VecReader::new(&b[..]).unwrap().limit(2).map(|e| e.unwrap().0).collect();
+        assert_eq!(limited, vec!["</s>", "cheese"]);
+
+        let vocab = vec!["sauce".to_string(), "potato".to_string()].into_iter().collect();
+        let filtered: Vec<_> = VecReader::new(&b[..]).unwrap().vocabulary(vocab).map(|e| e.unwrap().0).collect();
+        assert_eq!(filtered, vec!["sauce"]);
+
+        let vocab = vec!["</s>".to_string(), "sauce".to_string()].into_iter().collect();
+        let both: Vec<_> = VecReader::new(&b[..]).unwrap().vocabulary(vocab).limit(2).map(|e| e.unwrap().0).collect();
+        assert_eq!(both, vec!["</s>", "sauce"]);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(VecReader::new(&b"cheese 1 2\n"[..]).is_err());
+        let mut r = VecReader::new(&b"1 3\ncheese 1 2\n"[..]).unwrap();
+        assert_eq!(r.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}